@@ -0,0 +1,321 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::map::{rect_contains, DungeonMap, Region, Tile, DIRECTIONS};
+use crate::TreeError;
+
+//A walker that staggers about digging floor until enough of the map is open
+#[derive(Clone, Debug, PartialEq)]
+pub struct DrunkardGenerator {
+    //Share of the tiles inside the outer wall to dig out, 0 to 1
+    pub coverage: f32,
+    //Steps before the walker gives up and starts again from somewhere already dug
+    pub walk_length: usize,
+}
+
+impl Default for DrunkardGenerator {
+    fn default() -> DrunkardGenerator {
+        DrunkardGenerator {
+            coverage: 0.4,
+            walk_length: 400,
+        }
+    }
+}
+
+impl DrunkardGenerator {
+    //Starts in the middle of bounds. The outer edge is never dug so there's always room for a wall.
+    //What's dug out is region 0. The same seed gives the same map.
+    pub fn generate(&self, bounds: (i32, i32, i32, i32), seed: u64) -> Result<DungeonMap, TreeError> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut map = DungeonMap::new(bounds);
+        let inner = (bounds.0 + 1, bounds.1 + 1, bounds.2 - 1, bounds.3 - 1);
+
+        if inner.0 >= inner.2 || inner.1 >= inner.3 {
+            return Err(TreeError::EmptyMapError);
+        }
+
+        let area = ((inner.2 - inner.0) * (inner.3 - inner.1)) as usize;
+        let wanted = ((area as f32 * self.coverage.clamp(0.0, 1.0)) as usize).max(1);
+        //Stops a walker that can't reach the coverage from going forever
+        let max_steps = area * 50;
+
+        let start = ((inner.0 + inner.2) / 2, (inner.1 + inner.3) / 2);
+        let mut dug: Vec<(i32, i32)> = vec![start];
+        map.set(start, Tile::Floor);
+
+        let mut curr = start;
+        let mut walked = 0;
+
+        for _ in 0..max_steps {
+            if dug.len() >= wanted {
+                break;
+            }
+
+            if walked >= self.walk_length {
+                curr = dug[rng.gen_range(0..dug.len())];
+                walked = 0;
+            }
+
+            let (dx, dy) = DIRECTIONS[rng.gen_range(0..DIRECTIONS.len())];
+            let next = (curr.0 + dx, curr.1 + dy);
+            if !rect_contains(inner, next) {
+                continue;
+            }
+
+            if map.get(next) == Some(Tile::Empty) {
+                map.set(next, Tile::Floor);
+                dug.push(next);
+            }
+            curr = next;
+            walked += 1;
+        }
+
+        map.wall_in();
+        map.add_region(Region {
+            id: 0,
+            bounds: DungeonMap::bounding_box(&dug),
+            cells: dug,
+        });
+
+        Ok(map)
+    }
+}
+
+//Agents that dig corridors in straight runs, turning now and then, leaving rooms beside their corridors
+//and splitting off new agents along the way
+#[derive(Clone, Debug, PartialEq)]
+pub struct TunnelerGenerator {
+    //Steps each agent digs before stopping
+    pub lifetime: usize,
+    //Chance each step that an agent turns left or right
+    pub turn_chance: f32,
+    //Chance each step that an agent tries to put a room beside its corridor
+    pub room_chance: f32,
+    //Chance each step that an agent splits off a new one heading left or right
+    pub spawn_chance: f32,
+    //Most agents started over the whole run, the first included
+    pub max_agents: usize,
+    //Smallest and largest width and height of a room's floor
+    pub room_size: (i32, i32),
+}
+
+impl Default for TunnelerGenerator {
+    fn default() -> TunnelerGenerator {
+        TunnelerGenerator {
+            lifetime: 60,
+            turn_chance: 0.1,
+            room_chance: 0.08,
+            spawn_chance: 0.05,
+            max_agents: 20,
+            room_size: (3, 8),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Tunneler {
+    position: (i32, i32),
+    dir: (i32, i32),
+    steps: usize,
+}
+
+impl TunnelerGenerator {
+    //Corridors are dug as corridor, rooms as floor with a door onto the corridor that made them.
+    //Each room is a region numbered from 0 in the order they were made, with bounds taking in its walls.
+    //Nothing is dug on the outer edge of bounds. The same seed gives the same map.
+    pub fn generate(&self, bounds: (i32, i32, i32, i32), seed: u64) -> Result<DungeonMap, TreeError> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut map = DungeonMap::new(bounds);
+        let inner = (bounds.0 + 1, bounds.1 + 1, bounds.2 - 1, bounds.3 - 1);
+
+        if inner.0 >= inner.2 || inner.1 >= inner.3 {
+            return Err(TreeError::EmptyMapError);
+        }
+
+        let inside = |point: (i32, i32)| rect_contains(inner, point);
+
+        let start = ((inner.0 + inner.2) / 2, (inner.1 + inner.3) / 2);
+        map.set(start, Tile::Corridor);
+
+        let mut agents: Vec<Tunneler> = vec![Tunneler {
+            position: start,
+            dir: DIRECTIONS[rng.gen_range(0..DIRECTIONS.len())],
+            steps: 0,
+        }];
+        let mut started = 1;
+        let mut rooms: Vec<Region> = Vec::new();
+
+        while let Some(mut agent) = agents.pop() {
+            while agent.steps < self.lifetime {
+                agent.steps += 1;
+
+                if rng.gen::<f32>() < self.turn_chance {
+                    agent.dir = TunnelerGenerator::turn(agent.dir, rng.gen());
+                }
+
+                //Turn away from the edge and from rooms rather than digging into them
+                let left: bool = rng.gen();
+                let dir = [agent.dir, TunnelerGenerator::turn(agent.dir, left), TunnelerGenerator::turn(agent.dir, !left)]
+                    .into_iter()
+                    .find(|dir| self.can_dig(&map, (agent.position.0 + dir.0, agent.position.1 + dir.1), *dir, &inside));
+
+                agent.dir = match dir {
+                    Some(dir) => dir,
+                    None => break,
+                };
+                let next = (agent.position.0 + agent.dir.0, agent.position.1 + agent.dir.1);
+
+                map.set(next, Tile::Corridor);
+                agent.position = next;
+
+                if rng.gen::<f32>() < self.room_chance {
+                    let side = TunnelerGenerator::turn(agent.dir, rng.gen());
+                    if let Some(room) = self.place_room(&mut map, agent.position, side, rooms.len(), &inside, &mut rng) {
+                        rooms.push(room);
+                    }
+                }
+
+                if started < self.max_agents && rng.gen::<f32>() < self.spawn_chance {
+                    agents.push(Tunneler {
+                        position: agent.position,
+                        dir: TunnelerGenerator::turn(agent.dir, rng.gen()),
+                        steps: 0,
+                    });
+                    started += 1;
+                }
+            }
+        }
+
+        map.wall_in();
+        for room in rooms {
+            map.add_region(room);
+        }
+
+        Ok(map)
+    }
+
+    //A quarter turn, to the left or the right
+    fn turn(dir: (i32, i32), left: bool) -> (i32, i32) {
+        match left {
+            true => (dir.1, -dir.0),
+            false => (-dir.1, dir.0),
+        }
+    }
+
+    //Inside the outer edge and either empty or already corridor, with no room floor beside it.
+    //Fresh corridor can't run right alongside old corridor, so corridors only meet by crossing.
+    fn can_dig(&self, map: &DungeonMap, point: (i32, i32), dir: (i32, i32), inside: &impl Fn((i32, i32)) -> bool) -> bool {
+        let tile = map.get(point);
+        let beside = |dir: (i32, i32)| map.get((point.0 + dir.0, point.1 + dir.1));
+
+        inside(point)
+            && matches!(tile, Some(Tile::Empty) | Some(Tile::Corridor))
+            && DIRECTIONS.iter().all(|dir| !matches!(beside(*dir), Some(Tile::Floor) | Some(Tile::Door)))
+            && (tile == Some(Tile::Corridor)
+                || [TunnelerGenerator::turn(dir, true), TunnelerGenerator::turn(dir, false)]
+                    .iter()
+                    .all(|side| beside(*side) != Some(Tile::Corridor)))
+    }
+
+    //Tries to fit a room off to one side of the corridor at point, joined to it by a door.
+    //The room and the wall around it have to land on empty tiles inside the outer edge.
+    fn place_room<R: Rng>(
+        &self,
+        map: &mut DungeonMap,
+        point: (i32, i32),
+        side: (i32, i32),
+        id: usize,
+        inside: &impl Fn((i32, i32)) -> bool,
+        rng: &mut R,
+    ) -> Option<Region> {
+        let (low, high) = (self.room_size.0.max(1), self.room_size.1.max(self.room_size.0.max(1)));
+        let width = rng.gen_range(low..=high);
+        let height = rng.gen_range(low..=high);
+
+        let door = (point.0 + side.0, point.1 + side.1);
+        //Slide the room along the corridor so the door lands somewhere along its wall
+        let (x1, y1) = match side {
+            (0, -1) => (door.0 - rng.gen_range(0..width), door.1 - height),
+            (0, 1) => (door.0 - rng.gen_range(0..width), door.1 + 1),
+            (1, 0) => (door.0 + 1, door.1 - rng.gen_range(0..height)),
+            _ => (door.0 - width, door.1 - rng.gen_range(0..height)),
+        };
+        //Walls included
+        let bounds = (x1 - 1, y1 - 1, x1 + width + 1, y1 + height + 1);
+
+        let clear = (bounds.1..bounds.3)
+            .flat_map(|y| (bounds.0..bounds.2).map(move |x| (x, y)))
+            .all(|cell| inside(cell) && map.get(cell) == Some(Tile::Empty));
+        if !clear {
+            return None;
+        }
+
+        let mut cells: Vec<(i32, i32)> = Vec::new();
+        for y in y1..y1 + height {
+            for x in x1..x1 + width {
+                map.set((x, y), Tile::Floor);
+                cells.push((x, y));
+            }
+        }
+        map.set(door, Tile::Door);
+
+        Some(Region { id, bounds, cells })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DrunkardGenerator, TunnelerGenerator};
+    use crate::map::Tile;
+
+    #[test]
+    fn drunkards_dig_one_connected_cave_of_the_coverage_asked_for() {
+        for (seed, coverage) in (0..10).zip([0.2, 0.4, 0.6].into_iter().cycle()) {
+            let drunkard = DrunkardGenerator {
+                coverage,
+                ..DrunkardGenerator::default()
+            };
+            let map = drunkard.generate((0, 0, 60, 40), seed).unwrap();
+
+            let pockets = map.pockets();
+            assert_eq!(pockets.len(), 1, "\n{}", map.to_ascii());
+            assert_eq!(map.regions()[0].cells.len(), pockets[0].len());
+            //Inside the outer edge
+            let wanted = (58.0 * 38.0 * coverage) as usize;
+            assert_eq!(pockets[0].len(), wanted, "\n{}", map.to_ascii());
+
+            assert_eq!(drunkard.generate((0, 0, 60, 40), seed).unwrap().to_ascii(), map.to_ascii());
+        }
+    }
+
+    #[test]
+    fn tunnelers_join_every_room_they_dig() {
+        let mut rooms = 0;
+
+        for seed in 0..10 {
+            let tunneler = TunnelerGenerator::default();
+            let map = tunneler.generate((0, 0, 80, 50), seed).unwrap();
+            assert_eq!(map.pockets().len(), 1, "\n{}", map.to_ascii());
+
+            let mut seen: Vec<(i32, i32)> = Vec::new();
+            for region in map.regions() {
+                let (low, high) = tunneler.room_size;
+                let (width, height) = (region.bounds.2 - region.bounds.0 - 2, region.bounds.3 - region.bounds.1 - 2);
+                assert!((low..=high).contains(&width) && (low..=high).contains(&height));
+                assert!(region.cells.iter().all(|cell| map.get(*cell) == Some(Tile::Floor)));
+                assert!(region.cells.iter().all(|cell| !seen.contains(cell)), "rooms overlap\n{}", map.to_ascii());
+                seen.extend(region.cells.iter().copied());
+            }
+            rooms += map.regions().len();
+
+            //No rooms when none are asked for
+            let corridors_only = TunnelerGenerator {
+                room_chance: 0.0,
+                ..TunnelerGenerator::default()
+            };
+            assert!(corridors_only.generate((0, 0, 80, 50), seed).unwrap().regions().is_empty());
+        }
+
+        assert!(rooms > 0);
+    }
+}
@@ -0,0 +1,137 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::map::{DungeonMap, Region, Tile};
+use crate::TreeError;
+
+//Cellular automata caves. Rock is scattered at random, then smoothed by counting the rock around each tile,
+//with out of bounds counting as rock.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaveGenerator {
+    //Chance each tile starts out as rock
+    pub fill: f32,
+    //Smoothing passes
+    pub iterations: usize,
+    //Open tiles with at least this many of their 8 neighbours rock turn into rock
+    pub birth: usize,
+    //Rock with at least this many of its 8 neighbours rock stays rock, otherwise it opens up
+    pub survival: usize,
+    //Open pockets with fewer tiles than this are filled in. The biggest pocket is always kept.
+    pub min_pocket: usize,
+}
+
+impl Default for CaveGenerator {
+    fn default() -> CaveGenerator {
+        CaveGenerator {
+            fill: 0.45,
+            iterations: 5,
+            birth: 5,
+            survival: 4,
+            min_pocket: 30,
+        }
+    }
+}
+
+impl CaveGenerator {
+    //Floor for open ground with a wall around it. Each pocket left is a region, numbered from 0 biggest first.
+    //The same seed gives the same cave.
+    pub fn generate(&self, bounds: (i32, i32, i32, i32), seed: u64) -> Result<DungeonMap, TreeError> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut map = DungeonMap::new(bounds);
+        let (x1, y1, x2, y2) = bounds;
+
+        //The outer edge stays rock so the wall around the cave fits in bounds
+        let is_edge = |(x, y): (i32, i32)| x == x1 || y == y1 || x == x2 - 1 || y == y2 - 1;
+
+        let mut rock: Vec<bool> = (0..map.len())
+            .map(|idx| is_edge(map.point(idx)) || rng.gen::<f32>() < self.fill)
+            .collect();
+
+        for _ in 0..self.iterations {
+            rock = (0..map.len())
+                .map(|idx| {
+                    let point = map.point(idx);
+                    if is_edge(point) {
+                        return true;
+                    }
+
+                    let around = (-1..=1)
+                        .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                        .filter(|(dx, dy)| (*dx, *dy) != (0, 0))
+                        .filter(|(dx, dy)| map.index((point.0 + dx, point.1 + dy)).is_none_or(|next| rock[next]))
+                        .count();
+
+                    match rock[idx] {
+                        true => around >= self.survival,
+                        false => around >= self.birth,
+                    }
+                })
+                .collect();
+        }
+
+        for (idx, is_rock) in rock.iter().enumerate() {
+            if !is_rock {
+                map.set(map.point(idx), Tile::Floor);
+            }
+        }
+
+        let mut pockets = map.pockets();
+        if pockets.is_empty() {
+            return Err(TreeError::EmptyMapError);
+        }
+
+        for pocket in pockets.iter().skip(1).filter(|pocket| pocket.len() < self.min_pocket) {
+            for point in pocket.iter() {
+                map.set(*point, Tile::Empty);
+            }
+        }
+        let keep = 1 + pockets.iter().skip(1).filter(|pocket| pocket.len() >= self.min_pocket).count();
+        pockets.truncate(keep);
+
+        map.wall_in();
+
+        for (id, cells) in pockets.into_iter().enumerate() {
+            map.add_region(Region {
+                id,
+                bounds: DungeonMap::bounding_box(&cells),
+                cells,
+            });
+        }
+
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::CaveGenerator;
+
+    #[test]
+    fn caves_are_walled_pockets_of_a_fair_size() {
+        let cave = CaveGenerator::default();
+
+        for seed in 0..10 {
+            let map = cave.generate((0, 0, 80, 50), seed).unwrap();
+            let (x1, y1, x2, y2) = map.bounds;
+
+            //Every region is one whole pocket, biggest first, and nothing walkable is left out of them
+            let pockets: Vec<HashSet<(i32, i32)>> = map.pockets().into_iter().map(|pocket| pocket.into_iter().collect()).collect();
+            assert_eq!(pockets.len(), map.regions().len());
+            for region in map.regions() {
+                let cells: HashSet<(i32, i32)> = region.cells.iter().copied().collect();
+                assert!(pockets.contains(&cells), "region {} isn't one pocket\n{}", region.id, map.to_ascii());
+                assert!(region.id == 0 || cells.len() >= cave.min_pocket);
+                assert!(cells.len() <= map.regions()[0].cells.len());
+            }
+
+            let floor: Vec<(i32, i32)> = (0..map.len()).map(|idx| map.point(idx)).filter(|point| map.is_walkable(*point)).collect();
+            assert!(floor.iter().all(|(x, y)| *x > x1 && *y > y1 && *x < x2 - 1 && *y < y2 - 1), "floor on the edge\n{}", map.to_ascii());
+            let share = floor.len() as f32 / map.len() as f32;
+            assert!((0.25..0.75).contains(&share), "{} of the map is floor\n{}", share, map.to_ascii());
+
+            assert_eq!(cave.generate((0, 0, 80, 50), seed).unwrap().to_ascii(), map.to_ascii());
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use rand::Rng;
+
+use std::collections::HashSet;
+
+use crate::map::DIRECTIONS;
+use crate::room_graph::GraphNode;
+use crate::{DungeonPath, DungeonTree, TreeError};
+
+//Shape of the corridor gen_paths digs between two rooms. Every style ends exactly on the target point
+//and only ever steps north, south, east or west.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CorridorStyle {
+    //Along x, then along y
+    LShape,
+    //Along the longer axis to a random bend, across, then along the longer axis again
+    ZShape,
+    //Bresenham line, with a step inserted wherever it would move diagonally
+    Diagonal,
+    //Random walk drifting toward the target, kept inside the box between the two points
+    Meander,
+}
+
+impl CorridorStyle {
+    pub fn carve<R: Rng>(&self, from: (i32, i32), to: (i32, i32), rng: &mut R) -> DungeonPath {
+        let points = match self {
+            CorridorStyle::LShape => CorridorStyle::l_shape(from, to),
+            CorridorStyle::ZShape => CorridorStyle::z_shape(from, to, rng),
+            CorridorStyle::Diagonal => CorridorStyle::diagonal(from, to),
+            CorridorStyle::Meander => CorridorStyle::meander(from, to, rng),
+        };
+
+        DungeonPath::from_cells(&points)
+    }
+
+    //Walks from the last point in points to `to`, along x first then along y
+    pub(crate) fn push_leg(points: &mut Vec<(i32, i32)>, to: (i32, i32)) {
+        let mut curr = *points.last().unwrap();
+
+        while curr != to {
+            if curr.0 != to.0 {
+                curr.0 += (to.0 - curr.0).signum();
+            } else {
+                curr.1 += (to.1 - curr.1).signum();
+            }
+            points.push(curr);
+        }
+    }
+
+    fn l_shape(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+        let mut points = vec![from];
+        CorridorStyle::push_leg(&mut points, (to.0, from.1));
+        CorridorStyle::push_leg(&mut points, to);
+        points
+    }
+
+    fn z_shape<R: Rng>(from: (i32, i32), to: (i32, i32), rng: &mut R) -> Vec<(i32, i32)> {
+        let mut points = vec![from];
+
+        if (to.0 - from.0).abs() >= (to.1 - from.1).abs() {
+            let bend = rng.gen_range(i32::min(from.0, to.0)..=i32::max(from.0, to.0));
+            CorridorStyle::push_leg(&mut points, (bend, from.1));
+            CorridorStyle::push_leg(&mut points, (bend, to.1));
+        } else {
+            let bend = rng.gen_range(i32::min(from.1, to.1)..=i32::max(from.1, to.1));
+            CorridorStyle::push_leg(&mut points, (from.0, bend));
+            CorridorStyle::push_leg(&mut points, (to.0, bend));
+        }
+
+        CorridorStyle::push_leg(&mut points, to);
+        points
+    }
+
+    fn diagonal(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+        let dx = (to.0 - from.0).abs();
+        let dy = -(to.1 - from.1).abs();
+        let sx = (to.0 - from.0).signum();
+        let sy = (to.1 - from.1).signum();
+        let mut err = dx + dy;
+        let mut curr = from;
+        let mut points = vec![from];
+
+        while curr != to {
+            let e2 = 2 * err;
+            let step_x = e2 >= dy;
+            let step_y = e2 <= dx;
+
+            if step_x {
+                err += dy;
+                curr.0 += sx;
+                points.push(curr);
+            }
+            if step_y {
+                err += dx;
+                curr.1 += sy;
+                points.push(curr);
+            }
+        }
+
+        points
+    }
+
+    fn meander<R: Rng>(from: (i32, i32), to: (i32, i32), rng: &mut R) -> Vec<(i32, i32)> {
+        let (x1, x2) = (i32::min(from.0, to.0), i32::max(from.0, to.0));
+        let (y1, y2) = (i32::min(from.1, to.1), i32::max(from.1, to.1));
+        let max_steps = 4 * ((to.0 - from.0).abs() + (to.1 - from.1).abs());
+
+        let mut curr = from;
+        let mut points = vec![from];
+
+        for _ in 0..max_steps {
+            if curr == to {
+                return points;
+            }
+
+            let dist = (to.0 - curr.0).abs() + (to.1 - curr.1).abs();
+            let mut choices: Vec<((i32, i32), u32)> = Vec::new();
+
+            for (dx, dy) in DIRECTIONS {
+                let next = (curr.0 + dx, curr.1 + dy);
+                if next.0 < x1 || next.0 > x2 || next.1 < y1 || next.1 > y2 {
+                    continue;
+                }
+
+                //Steps toward the target are three times as likely as steps away from it
+                let closer = (to.0 - next.0).abs() + (to.1 - next.1).abs() < dist;
+                choices.push((next, if closer { 3 } else { 1 }));
+            }
+
+            let mut roll = rng.gen_range(0..choices.iter().map(|(_, weight)| weight).sum::<u32>());
+            for (next, weight) in choices {
+                if roll < weight {
+                    curr = next;
+                    break;
+                }
+                roll -= weight;
+            }
+
+            points.push(curr);
+        }
+
+        //Out of steps, so head straight there
+        CorridorStyle::push_leg(&mut points, (to.0, curr.1));
+        CorridorStyle::push_leg(&mut points, to);
+        points
+    }
+}
+
+impl DungeonTree {
+    //Style used by gen_paths for every connection without its own
+    pub fn set_corridor_style(&mut self, style: CorridorStyle) {
+        self.corridor_style = style;
+    }
+
+    //Style used by gen_paths between the rooms with these node_ids, in either order
+    pub fn set_connection_style(&mut self, room_a: usize, room_b: usize, style: CorridorStyle) {
+        self.connection_styles.insert((usize::min(room_a, room_b), usize::max(room_a, room_b)), style);
+    }
+
+    pub fn get_connection_style(&self, room_a: usize, room_b: usize) -> CorridorStyle {
+        *self
+            .connection_styles
+            .get(&(usize::min(room_a, room_b), usize::max(room_a, room_b)))
+            .unwrap_or(&self.corridor_style)
+    }
+
+    //Width used for every connection without its own
+    pub fn set_corridor_width(&mut self, width: i32) {
+        self.corridor_width = width.max(1);
+    }
+
+    //Width used between the rooms with these node_ids, in either order
+    pub fn set_connection_width(&mut self, room_a: usize, room_b: usize, width: i32) {
+        self.connection_widths.insert((usize::min(room_a, room_b), usize::max(room_a, room_b)), width.max(1));
+    }
+
+    pub fn get_connection_width(&self, room_a: usize, room_b: usize) -> i32 {
+        *self
+            .connection_widths
+            .get(&(usize::min(room_a, room_b), usize::max(room_a, room_b)))
+            .unwrap_or(&self.corridor_width)
+    }
+
+    //Widens the paths on the way from the entrance room to the exit room, leaving side passages as they are.
+    //Also sets the width for those connections, so paths dug for them later match.
+    pub fn widen_main_path(&mut self, width: i32) -> Result<(), TreeError> {
+        let placed = self.entrance_exit.as_ref().ok_or(TreeError::NoEntranceError)?;
+        let (start, exit) = (placed.entrance_room, placed.exit_room);
+
+        let parents = self.room_graph.walk(start, &HashSet::new());
+        if !parents.contains_key(&GraphNode::Room(exit)) {
+            return Err(TreeError::DisconnectedError);
+        }
+
+        //The way there can cross other corridors, so every path stepped along is widened, not just ones ending at rooms
+        let mut curr = GraphNode::Room(exit);
+        while let Some(Some((parent, path_idx))) = parents.get(&curr) {
+            self.paths[*path_idx].width = width.max(1);
+            if let Some((room_a, room_b)) = self.paths[*path_idx].rooms {
+                self.set_connection_width(room_a, room_b, width);
+            }
+            curr = *parent;
+        }
+        //Wider corridors can run into more of the dungeon
+        self.update_room_graph();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CorridorStyle;
+    use crate::map::rect_contains;
+    use crate::{DungeonNode, DungeonTree};
+
+    #[test]
+    fn every_style_joins_rooms_on_every_side() {
+        let bounds = (0, 0, 80, 60);
+        let room_a = (32, 24, 46, 34);
+        let styles = [CorridorStyle::LShape, CorridorStyle::ZShape, CorridorStyle::Diagonal, CorridorStyle::Meander];
+
+        for style in styles {
+            for (dx, dy) in (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy))).filter(|dir| *dir != (0, 0)) {
+                //Lined up with room_a, and shifted along a bit so the faces don't line up
+                for shift in [0, 5] {
+                    let (x, y) = (room_a.0 + dx * 26 + shift, room_a.1 + dy * 18 + shift);
+                    let room_b = (x, y, x + 14, y + 10);
+
+                    for seed in 0..5 {
+                        let mut tree = DungeonTree::new(1);
+                        tree.setRoot(DungeonNode {
+                            coords: Some(bounds),
+                            ..DungeonNode::new()
+                        })
+                        .unwrap();
+                        tree.build_from_rooms(&[room_a, room_b]).unwrap();
+                        tree.set_seed(seed);
+                        tree.set_corridor_style(style);
+
+                        let rooms = tree.get_subtree_rooms(0);
+                        let id = |rect| rooms.iter().find(|node| node.room == Some(rect)).unwrap().node_id;
+                        tree.connect_rooms(&[(id(room_a), id(room_b))]).unwrap();
+
+                        let cells: Vec<(i32, i32)> = tree.paths()[0].cells().collect();
+                        let case = format!("{:?} from {:?} to {:?}", style, room_a, room_b);
+                        assert!(rect_contains(room_a, cells[0]), "{} starts at {:?}", case, cells[0]);
+                        assert_eq!(cells.last().copied(), Some(DungeonTree::room_center(room_b)), "{}", case);
+                        assert!(cells.iter().all(|cell| rect_contains(bounds, *cell)), "{} leaves the map", case);
+                        for pair in cells.windows(2) {
+                            assert_eq!((pair[0].0 - pair[1].0).abs() + (pair[0].1 - pair[1].1).abs(), 1, "{} jumps", case);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
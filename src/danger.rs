@@ -0,0 +1,90 @@
+use crossterm::{
+    cursor,
+    style::{self, Stylize},
+    terminal, ExecutableCommand, QueueableCommand,
+};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::map::{rect_index, DungeonMap};
+use crate::{DungeonTree, TreeError};
+
+//Danger between 0 (at the entrance) and 1 (the walkable tile furthest from it), going by walking distance
+#[derive(Clone, Debug, PartialEq)]
+pub struct DangerMap {
+    pub bounds: (i32, i32, i32, i32),
+    //Indexed like DungeonMap tiles, None where the tile can't be walked to from the entrance
+    tiles: Vec<Option<f32>>,
+    //Region id (node_id for the tree's rooms) to the danger at the region's center
+    rooms: HashMap<usize, f32>,
+}
+
+impl DangerMap {
+    pub fn at(&self, point: (i32, i32)) -> Option<f32> {
+        rect_index(self.bounds, point).and_then(|idx| self.tiles[idx])
+    }
+
+    pub fn room(&self, node_id: usize) -> Option<f32> {
+        self.rooms.get(&node_id).copied()
+    }
+
+    //Draws every walkable tile to the terminal, green near the entrance through to red furthest from it
+    pub fn draw(&self) {
+        let mut stdout = io::stdout();
+        let width = (self.bounds.2 - self.bounds.0).max(1);
+
+        stdout.execute(terminal::Clear(terminal::ClearType::All)).unwrap();
+
+        for (idx, level) in self.tiles.iter().enumerate() {
+            if let Some(level) = level {
+                let point = (self.bounds.0 + idx as i32 % width, self.bounds.1 + idx as i32 / width);
+                let colr = style::Color::Rgb {
+                    r: (255.0 * level) as u8,
+                    g: (255.0 * (1.0 - level)) as u8,
+                    b: 0,
+                };
+
+                let _ = stdout
+                    .queue(cursor::MoveTo(point.0 as u16, point.1 as u16))
+                    .unwrap()
+                    .queue(style::PrintStyledContent("█".with(colr)));
+            }
+        }
+
+        stdout.flush().unwrap();
+    }
+}
+
+impl DungeonTree {
+    //place_entrance_exit must have been run first
+    pub fn danger_map(&self) -> Result<DangerMap, TreeError> {
+        let placed = self.entrance_exit.as_ref().ok_or(TreeError::NoEntranceError)?;
+
+        Ok(self.rasterize().danger_map(placed.entrance))
+    }
+}
+
+impl DungeonMap {
+    pub fn danger_map(&self, entrance: (i32, i32)) -> DangerMap {
+        let distances = self.distances_from(entrance);
+
+        let furthest = distances.iter().filter_map(|dist| *dist).max().unwrap_or(0).max(1) as f32;
+        let tiles: Vec<Option<f32>> = distances
+            .iter()
+            .map(|dist| dist.map(|dist| dist as f32 / furthest))
+            .collect();
+
+        let mut rooms: HashMap<usize, f32> = HashMap::new();
+        for region in self.regions() {
+            if let Some(danger) = region.center().and_then(|center| self.index(center)).and_then(|idx| tiles[idx]) {
+                rooms.insert(region.id, danger);
+            }
+        }
+
+        DangerMap {
+            bounds: self.bounds,
+            tiles,
+            rooms,
+        }
+    }
+}
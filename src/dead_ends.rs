@@ -0,0 +1,339 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::map::{brush_cells, rect_contains, DungeonMap, Tile, DIRECTIONS};
+use crate::{DungeonPath, DungeonTree};
+
+impl DungeonMap {
+    //Corridor tiles with at most one walkable neighbour. Only finds the ends of corridors one tile wide,
+    //so for a tree's paths use DungeonTree::find_dead_ends.
+    pub fn dead_ends(&self) -> Vec<(i32, i32)> {
+        (0..self.len())
+            .map(|idx| self.point(idx))
+            .filter(|point| self.get(*point) == Some(Tile::Corridor))
+            .filter(|point| self.neighbours(*point).iter().filter(|next| self.is_walkable(**next)).count() <= 1)
+            .collect()
+    }
+}
+
+impl DungeonTree {
+    //(path index, cell) of every dead end tip, see path_tips. Works from the paths rather than the map,
+    //so corridors of any width are seen.
+    pub fn find_dead_ends(&self) -> Vec<(usize, (i32, i32))> {
+        self.dead_end_tips()
+            .into_iter()
+            .map(|(path_idx, idx)| (path_idx, self.paths[path_idx].cells().nth(idx).unwrap()))
+            .collect()
+    }
+
+    //(path index, index into the path's cells) of every tip leading nowhere, see path_tips
+    fn dead_end_tips(&self) -> Vec<(usize, usize)> {
+        let rooms = self.room_rects();
+        let coverage = self.coverage();
+
+        (0..self.paths.len())
+            .flat_map(|path_idx| self.path_tips(path_idx, &rooms, &coverage).into_iter().map(move |idx| (path_idx, idx)))
+            .collect()
+    }
+
+    fn room_rects(&self) -> Vec<(i32, i32, i32, i32)> {
+        self.get_subtree_rooms(0).iter().map(|node| node.room.unwrap()).collect()
+    }
+
+    //How many path cells dig each tile, counting a cell again every time a path comes back through it
+    fn coverage(&self) -> HashMap<(i32, i32), usize> {
+        let mut coverage: HashMap<(i32, i32), usize> = HashMap::new();
+
+        for path in self.paths.iter() {
+            for tile in path.cells().flat_map(|cell| brush_cells(cell, path.width)) {
+                *coverage.entry(tile).or_insert(0) += 1;
+            }
+        }
+
+        coverage
+    }
+
+    //Index into the path's cells of every tip leading nowhere. Tips are the ends of a path and cells where it turns
+    //straight back on itself. One leads nowhere when its brush digs into no room, and nothing is dug on or beside the
+    //brush but other paths. The path's own cells count as other too, except the ones a brush's width either side of
+    //the tip that it's reached along and the tip's cell coming round again, as a meander going back and forth over
+    //the same cells does.
+    fn path_tips(&self, path_idx: usize, rooms: &[(i32, i32, i32, i32)], coverage: &HashMap<(i32, i32), usize>) -> Vec<usize> {
+        let path = &self.paths[path_idx];
+        let cells: Vec<(i32, i32)> = path.cells().collect();
+        let reach = path.width.max(1) as usize;
+        let mut tips: Vec<usize> = Vec::new();
+
+        for idx in 0..cells.len() {
+            let end = idx == 0 || idx == cells.len() - 1;
+            if !end && cells[idx - 1] != cells[idx + 1] {
+                continue;
+            }
+
+            //How many times each tile is dug by the cells near the tip and the tip's cell coming round again,
+            //so whatever is left of coverage is dug by something else
+            let near = idx.saturating_sub(reach)..=(idx + reach).min(cells.len() - 1);
+            let mut own: HashMap<(i32, i32), usize> = HashMap::new();
+            let mut near_dug: HashSet<(i32, i32)> = HashSet::new();
+            for (other, cell) in cells.iter().enumerate() {
+                let is_near = near.contains(&other);
+                if !is_near && *cell != cells[idx] {
+                    continue;
+                }
+                for tile in brush_cells(*cell, path.width) {
+                    *own.entry(tile).or_insert(0) += 1;
+                    if is_near {
+                        near_dug.insert(tile);
+                    }
+                }
+            }
+            let dug_by_other = |tile: &(i32, i32)| coverage.get(tile).copied().unwrap_or(0) > own.get(tile).copied().unwrap_or(0);
+
+            let dug: Vec<(i32, i32)> = brush_cells(cells[idx], path.width).collect();
+            let in_room = dug.iter().any(|tile| rooms.iter().any(|room| rect_contains(*room, *tile)));
+            let meets = dug
+                .iter()
+                .flat_map(|tile| DIRECTIONS.iter().map(move |(dx, dy)| (tile.0 + dx, tile.1 + dy)).chain([*tile]))
+                .filter(|tile| dug.contains(tile) || !near_dug.contains(tile))
+                .any(|tile| dug_by_other(&tile));
+
+            //A path cut down to one cell that other paths dig over anyway is left over from pruning a spur
+            let leftover = cells.len() == 1 && dug.iter().all(dug_by_other);
+
+            if (!in_room && !meets) || leftover {
+                tips.push(idx);
+            }
+        }
+
+        tips
+    }
+
+    //Eats every dead end back to the nearest junction or room, a cell at a time off its tip so paths stay unbroken.
+    //The entrance is left alone. Returns how many walkable tiles were removed.
+    //Works through a list of paths to look at, and only puts a path back on it when a tile on or beside it is dug less.
+    pub fn prune_dead_ends(&mut self) -> usize {
+        let walkable = |tree: &DungeonTree| {
+            let map = tree.rasterize();
+            (0..map.len()).filter(|idx| map.is_walkable(map.point(*idx))).count()
+        };
+        let before = walkable(self);
+        let entrance = self.entrance_exit.as_ref().map(|placed| placed.entrance);
+        let rooms = self.room_rects();
+        let mut coverage = self.coverage();
+
+        //Paths digging on or beside each tile
+        let mut diggers: HashMap<(i32, i32), HashSet<usize>> = HashMap::new();
+        for (path_idx, path) in self.paths.iter().enumerate() {
+            for tile in path.footprint() {
+                for near in DIRECTIONS.iter().map(|(dx, dy)| (tile.0 + dx, tile.1 + dy)).chain([tile]) {
+                    diggers.entry(near).or_default().insert(path_idx);
+                }
+            }
+        }
+
+        let mut queue: VecDeque<usize> = (0..self.paths.len()).collect();
+        let mut queued: Vec<bool> = vec![true; self.paths.len()];
+
+        while let Some(path_idx) = queue.pop_front() {
+            queued[path_idx] = false;
+
+            let tip = self
+                .path_tips(path_idx, &rooms, &coverage)
+                .into_iter()
+                .find(|idx| self.paths[path_idx].cells().nth(*idx) != entrance);
+            let idx = match tip {
+                Some(idx) => idx,
+                None => continue,
+            };
+
+            let path = &mut self.paths[path_idx];
+            let mut cells: Vec<(i32, i32)> = path.cells().collect();
+            //A turn back loses the tip and the repeat of the cell before it
+            let cut = if idx == 0 || idx == cells.len() - 1 { idx..idx + 1 } else { idx..idx + 2 };
+            let removed: Vec<(i32, i32)> = cells.drain(cut).collect();
+
+            *path = DungeonPath {
+                rooms: path.rooms,
+                width: path.width,
+                ..DungeonPath::from_cells(&cells)
+            };
+
+            for tile in removed.iter().flat_map(|cell| brush_cells(*cell, path.width)) {
+                *coverage.get_mut(&tile).unwrap() -= 1;
+
+                for other in diggers.get(&tile).into_iter().flatten() {
+                    if !queued[*other] {
+                        queued[*other] = true;
+                        queue.push_back(*other);
+                    }
+                }
+            }
+        }
+
+        self.remove_path_cells(&HashSet::new());
+
+        before - walkable(self)
+    }
+
+    //Takes the cells out of every path, then drops paths with nothing left
+    pub(crate) fn remove_path_cells(&mut self, removed: &HashSet<(i32, i32)>) {
+        for path in self.paths.iter_mut() {
+            path.remove_cells(removed);
+        }
+
+        //Keep locked doors pointed at the right paths
+        let mut new_idx: Vec<Option<usize>> = Vec::with_capacity(self.paths.len());
+        let mut next = 0;
+        for path in self.paths.iter() {
+            match path.is_empty() {
+                true => new_idx.push(None),
+                false => {
+                    new_idx.push(Some(next));
+                    next += 1;
+                }
+            }
+        }
+        self.paths.retain(|path| !path.is_empty());
+
+        if let Some(progression) = self.progression.as_mut() {
+            progression.doors.retain(|door| new_idx[door.path_idx].is_some());
+            for door in progression.doors.iter_mut() {
+                door.path_idx = new_idx[door.path_idx].unwrap();
+            }
+        }
+
+        //Spurs whose tips are no longer dug
+        let paths = &self.paths;
+        self.dead_ends.retain(|end| paths.iter().any(|path| path.cells().any(|cell| cell == *end)));
+        self.update_room_graph();
+    }
+
+    //Digs up to count straight spurs off existing corridors, each length.0 to length.1 tiles long and as wide as
+    //set_corridor_width, only through empty space inside the mask so they don't run into anything. Returns the tip
+    //of each one, which populate treats as a RoomRole::DeadEnd for secrets and traps.
+    pub fn add_dead_ends(&mut self, count: usize, length: (i32, i32)) -> Vec<(i32, i32)> {
+        let mut rng = self.fork_rng();
+        let mut map = self.routing_map();
+        let width = self.corridor_width;
+        let mut tips: Vec<(i32, i32)> = Vec::new();
+
+        //Spurs start from the cells of corridors at least as wide, so the brush at the start is already dug
+        let mut starts: Vec<(i32, i32)> = self
+            .paths
+            .iter()
+            .filter(|path| path.width >= width)
+            .flat_map(|path| path.cells())
+            .filter(|point| map.get(*point) == Some(Tile::Corridor))
+            .collect();
+        starts.sort();
+        starts.dedup();
+        starts.shuffle(&mut rng);
+
+        for start in starts {
+            if tips.len() >= count {
+                break;
+            }
+
+            let mut dirs = DIRECTIONS;
+            dirs.shuffle(&mut rng);
+            let wanted = rng.gen_range(length.0.max(1)..=length.1.max(length.0.max(1)));
+
+            for (dx, dy) in dirs {
+                let mut cells: Vec<(i32, i32)> = vec![start];
+
+                for step in 1..=wanted {
+                    let cell = (start.0 + dx * step, start.1 + dy * step);
+                    let prev = (cell.0 - dx, cell.1 - dy);
+                    //The brush here, a tile either side of it and the brush a step ahead, less what the step before covered
+                    let around = |center: (i32, i32)| [center, (center.0 + dy, center.1 + dx), (center.0 - dy, center.1 - dx)];
+                    let behind: HashSet<(i32, i32)> = around(prev).into_iter().flat_map(|center| brush_cells(center, width)).collect();
+
+                    let clear = around(cell)
+                        .into_iter()
+                        .chain([(cell.0 + dx, cell.1 + dy)])
+                        .flat_map(|center| brush_cells(center, width))
+                        .filter(|point| !behind.contains(point))
+                        .all(|point| map.get(point) == Some(Tile::Empty));
+                    if !clear {
+                        break;
+                    }
+                    cells.push(cell);
+                }
+
+                if (cells.len() as i32 - 1) < length.0.max(1) {
+                    continue;
+                }
+
+                for cell in cells.iter() {
+                    map.carve_brush(*cell, width);
+                }
+                tips.push(*cells.last().unwrap());
+                self.paths.push(DungeonPath {
+                    width,
+                    ..DungeonPath::from_cells(&cells)
+                });
+                break;
+            }
+        }
+
+        self.dead_ends.extend(tips.iter().copied());
+        self.update_room_graph();
+
+        tips
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::corridor::CorridorStyle;
+    use crate::map::brush_cells;
+    use crate::{split_dirs, Dungeon};
+
+    #[test]
+    fn dead_ends_are_pruned_at_any_width() {
+        let mut pruned = 0;
+
+        let cases = [(3, CorridorStyle::LShape), (1, CorridorStyle::Meander), (2, CorridorStyle::ZShape)].into_iter().cycle();
+        for (seed, (width, style)) in (0..60).zip(cases) {
+            let mut dungeon = Dungeon::new((0, 0, 80, 60), 4, split_dirs::RANDOM);
+            dungeon.tree.set_seed(seed);
+            dungeon.split_all((2, 2, 2, 2)).unwrap();
+            dungeon.tree.build_rooms((2, 2, 2, 2)).unwrap();
+            dungeon.tree.set_corridor_width(width);
+            dungeon.tree.set_corridor_style(style);
+            dungeon.tree.gen_paths().unwrap();
+            let spurs = dungeon.tree.add_dead_ends(4, (4, 8));
+            //Dug as wide as the rest, so each one is found as a dead end
+            let ends = dungeon.tree.find_dead_ends();
+            for tip in spurs {
+                assert!(ends.iter().any(|(path_idx, end)| *end == tip && dungeon.tree.paths[*path_idx].width == width));
+            }
+
+            let pockets = dungeon.tree.rasterize().pockets().len();
+            dungeon.tree.prune_dead_ends();
+            let map = dungeon.tree.rasterize();
+
+            assert!(dungeon.tree.find_dead_ends().is_empty());
+            assert_eq!(map.pockets().len(), pockets, "{}", map.to_ascii());
+            if width == 1 {
+                assert!(map.dead_ends().is_empty(), "{:?}\n{}", map.dead_ends(), map.to_ascii());
+            }
+            for (_, tip) in ends {
+                //All that can be left is the brush around the end that meets something
+                let at_end = dungeon.tree.paths.iter().any(|path| {
+                    path.start().into_iter().chain(path.end()).any(|end| brush_cells(end, path.width).any(|tile| tile == tip))
+                });
+                assert!(!map.is_walkable(tip) || at_end, "{:?} left dug\n{}", tip, map.to_ascii());
+                pruned += 1;
+            }
+
+            for path in dungeon.tree.paths.iter() {
+                let cells: Vec<(i32, i32)> = path.cells().collect();
+                assert!(cells.windows(2).all(|pair| (pair[1].0 - pair[0].0).abs() + (pair[1].1 - pair[0].1).abs() == 1));
+            }
+        }
+
+        assert!(pruned > 0);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::map::{DungeonMap, DIRECTIONS};
+use crate::router::RouteCosts;
+use crate::{DungeonPath, DungeonTree, TreeError};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntranceExit {
+    pub entrance: (i32, i32),
+    pub exit: (i32, i32),
+    //node_id of the rooms the entrance leads into and the exit sits in
+    pub entrance_room: usize,
+    pub exit_room: usize,
+    //Walking distance from the entrance to the exit
+    pub distance: usize,
+}
+
+impl DungeonMap {
+    //Of the walkable points, the pair (a, b, distance) furthest apart to walk between,
+    //with extra[a] added on to the walk from a
+    pub(crate) fn furthest_apart(&self, points: &[(i32, i32)], extra: &[usize]) -> Option<(usize, usize, usize)> {
+        let mut best: Option<(usize, usize, usize)> = None;
+
+        for (a, point) in points.iter().enumerate() {
+            let distances = self.distances_from(*point);
+
+            for (b, other) in points.iter().enumerate() {
+                if let Some(dist) = self.index(*other).and_then(|idx| distances[idx]) {
+                    let dist = dist + extra.get(a).copied().unwrap_or(0);
+                    if best.is_none_or(|best| dist > best.2) {
+                        best = Some((a, b, dist));
+                    }
+                }
+            }
+        }
+
+        best
+    }
+
+    //Puts the entrance and exit on the two region tiles furthest apart to walk between, found by walking out
+    //from a region's center to the furthest region tile, then out again from there.
+    //entrance_room and exit_room are the ids of the regions they end up in.
+    pub fn place_entrance_exit(&self) -> Result<EntranceExit, TreeError> {
+        let start = self.regions().iter().find_map(|region| region.center()).ok_or(TreeError::EmptyMapError)?;
+
+        let furthest = |from: (i32, i32)| {
+            let distances = self.distances_from(from);
+            self.regions()
+                .iter()
+                .flat_map(|region| region.cells.iter().map(move |cell| (region.id, *cell)))
+                .filter_map(|(id, cell)| self.index(cell).and_then(|idx| distances[idx]).map(|dist| (id, cell, dist)))
+                .max_by_key(|(_, _, dist)| *dist)
+                .unwrap()
+        };
+
+        let (entrance_room, entrance, _) = furthest(start);
+        let (exit_room, exit, distance) = furthest(entrance);
+
+        Ok(EntranceExit {
+            entrance,
+            exit,
+            entrance_room,
+            exit_room,
+            distance,
+        })
+    }
+}
+
+impl DungeonTree {
+    //Puts the entrance and exit in the pair of rooms that are the furthest apart to walk between.
+    //With on_edge the entrance is moved out to the nearest edge of the map, or of the mask if there is one, and a corridor
+    //is routed from it to its room, failing with NoRouteError if other rooms are in the way all round.
+    //gen_paths must have been run first.
+    pub fn place_entrance_exit(&mut self, on_edge: bool) -> Result<EntranceExit, TreeError> {
+        let map = self.routing_map();
+
+        let rooms: Vec<(usize, (i32, i32))> = self
+            .get_leaves()?
+            .iter()
+            .filter_map(|node| *node)
+            .filter_map(|node| node.room.map(|room| (node.node_id, DungeonTree::room_center(room))))
+            .filter(|(_, center)| map.is_walkable(*center))
+            .collect();
+        let rects: Vec<(i32, i32, i32, i32)> = rooms.iter().map(|(node_id, _)| self.nodes[*node_id].unwrap().room.unwrap()).collect();
+
+        if rooms.is_empty() {
+            return Err(TreeError::NoLeavesError);
+        }
+
+        //Where the entrance would go for each room, and how far it is from the room's center
+        let entrances: Vec<((i32, i32), usize)> = rooms
+            .iter()
+            .map(|(_, center)| match on_edge {
+                true => self.nearest_edge(&map, *center),
+                false => (*center, 0),
+            })
+            .collect();
+
+        let centers: Vec<(i32, i32)> = rooms.iter().map(|(_, center)| *center).collect();
+        let extra: Vec<usize> = entrances.iter().map(|(_, dist)| *dist).collect();
+        let (entrance_idx, exit_idx, _) = map.furthest_apart(&centers, &extra).unwrap();
+        let (entrance, _) = entrances[entrance_idx];
+
+        if on_edge {
+            let route = map
+                .find_wide_route(rooms[entrance_idx].1, entrance, &RouteCosts::default(), self.corridor_width, &[rects[entrance_idx]])
+                .ok_or(TreeError::NoRouteError)?;
+            self.paths.push(DungeonPath {
+                width: self.corridor_width,
+                ..DungeonPath::from_cells(&route)
+            });
+            self.update_room_graph();
+        }
+
+        //Walk it again now the spur is dug
+        let map = self.rasterize();
+        let exit = rooms[exit_idx].1;
+        let distance = map.distances_from(entrance)[map.index(exit).unwrap()].ok_or(TreeError::DisconnectedError)?;
+
+        let placed = EntranceExit {
+            entrance,
+            exit,
+            entrance_room: rooms[entrance_idx].0,
+            exit_room: rooms[exit_idx].0,
+            distance,
+        };
+        self.entrance_exit = Some(placed.clone());
+
+        Ok(placed)
+    }
+
+    //The closest point on the edge of the map or mask in a straight line from point, and how far away it is
+    fn nearest_edge(&self, map: &DungeonMap, point: (i32, i32)) -> ((i32, i32), usize) {
+        let inside = |cell: (i32, i32)| map.index(cell).is_some() && self.mask.as_ref().is_none_or(|mask| mask.contains(cell));
+
+        DIRECTIONS
+            .iter()
+            .map(|(dx, dy)| {
+                let mut dist = 0;
+                while inside((point.0 + dx * (dist + 1), point.1 + dy * (dist + 1))) {
+                    dist += 1;
+                }
+                ((point.0 + dx * dist, point.1 + dy * dist), dist as usize)
+            })
+            .min_by_key(|(_, dist)| *dist)
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{split_dirs, Dungeon, TreeError};
+
+    #[test]
+    fn entrance_and_exit_are_the_furthest_walkable_pair() {
+        let mut on_edge_placed = 0;
+
+        for seed in 0..30 {
+            let on_edge = seed % 2 == 1;
+            let mut dungeon = Dungeon::new((0, 0, 80, 60), 4, split_dirs::RANDOM);
+            dungeon.tree.set_seed(seed);
+            dungeon.split_all((2, 2, 2, 2)).unwrap();
+            dungeon.tree.build_rooms((2, 2, 2, 2)).unwrap();
+            dungeon.tree.set_corridor_width(1 + seed as i32 % 3);
+            dungeon.tree.gen_paths().unwrap();
+
+            let centers: Vec<(i32, i32)> = dungeon
+                .tree
+                .get_leaves()
+                .unwrap()
+                .iter()
+                .filter_map(|node| node.and_then(|node| node.room))
+                .map(crate::DungeonTree::room_center)
+                .collect();
+            let placed = match dungeon.tree.place_entrance_exit(on_edge) {
+                Ok(placed) => placed,
+                Err(TreeError::NoRouteError) if on_edge => continue,
+                Err(err) => panic!("{}", err),
+            };
+            let map = dungeon.tree.rasterize();
+
+            assert!(map.is_walkable(placed.entrance) && map.is_walkable(placed.exit));
+            assert_eq!(map.distances_from(placed.entrance)[map.index(placed.exit).unwrap()], Some(placed.distance));
+
+            if on_edge {
+                let (x1, y1, x2, y2) = map.bounds;
+                assert!([x1, x2 - 1].contains(&placed.entrance.0) || [y1, y2 - 1].contains(&placed.entrance.1));
+                on_edge_placed += 1;
+                continue;
+            }
+
+            let map = &map;
+            let furthest = centers
+                .iter()
+                .filter(|center| map.is_walkable(**center))
+                .flat_map(|center| {
+                    let distances = map.distances_from(*center);
+                    centers.iter().filter_map(move |other| map.index(*other).and_then(|idx| distances[idx]))
+                })
+                .max()
+                .unwrap();
+            assert_eq!(placed.distance, furthest);
+        }
+
+        assert!(on_edge_placed > 10);
+    }
+}
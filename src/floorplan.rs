@@ -0,0 +1,244 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::HashSet;
+
+use crate::map::rect_contains;
+use crate::room_graph::PlacedRoom;
+use crate::{Dungeon, DungeonPath, DungeonTree, TreeError};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FloorplanSettings {
+    //Chance of a door in each shared wall the tree didn't already put one in, for loops
+    pub extra_doors: f32,
+    //Whether to put a door in the outer wall
+    pub entrance: bool,
+}
+
+impl Default for FloorplanSettings {
+    fn default() -> FloorplanSettings {
+        FloorplanSettings {
+            extra_doors: 0.1,
+            entrance: true,
+        }
+    }
+}
+
+impl Dungeon {
+    //Like generate, but for the inside of a building. Rooms fill the whole of their sub-dungeon
+    //and neighbouring rooms share a wall, with doors cut straight through it instead of corridors.
+    pub fn generate_floorplan(&mut self, settings: &FloorplanSettings) -> Result<(), TreeError> {
+        //Splits as if rooms were inset a tile, which keeps most sub-dungeons wide enough for floor
+        self.split_all((1, 1, 1, 1))?;
+        self.tree.build_floorplan(settings)
+    }
+}
+
+impl DungeonTree {
+    //Gives every leaf a room covering its part of the root, with the wall between two sub-dungeons shared by both sides
+    //and the root's edge as the outer wall. A door goes in the wall across each split, joining a room on one side to
+    //a room on the other, so every room can be reached. Each door is a one tile path joining its two rooms.
+    pub fn build_floorplan(&mut self, settings: &FloorplanSettings) -> Result<(), TreeError> {
+        let mut rng = self.fork_rng();
+        let root = self.nodes.first().copied().flatten().ok_or(TreeError::NoLeavesError)?;
+
+        self.paths.clear();
+        self.interiors.clear();
+
+        //Each node's share of the root, walls included, handed down from the top
+        let mut frontier: Vec<(usize, (i32, i32, i32, i32))> = vec![(0, root.coords.unwrap())];
+        while let Some((idx, cell)) = frontier.pop() {
+            let node = self.nodes[idx].unwrap();
+
+            match (node.left, node.right) {
+                (Some(left), Some(right)) => {
+                    let (left_coords, right_coords) = (self.nodes[left].unwrap().coords.unwrap(), self.nodes[right].unwrap().coords.unwrap());
+
+                    //The row or column the split left between the two children becomes their shared wall
+                    let (left_cell, right_cell) = match left_coords.2 < right_coords.0 {
+                        true => ((cell.0, cell.1, left_coords.2 + 1, cell.3), (left_coords.2, cell.1, cell.2, cell.3)),
+                        false => ((cell.0, cell.1, cell.2, left_coords.3 + 1), (cell.0, left_coords.3, cell.2, cell.3)),
+                    };
+
+                    //A side too thin for floor would be a solid block of wall cutting its neighbours off, so the split is undone
+                    if !DungeonTree::has_floor(left_cell) || !DungeonTree::has_floor(right_cell) {
+                        self.remove_at_idx(left as i32);
+                        self.remove_at_idx(right as i32);
+                        let node = self.nodes[idx].as_mut().unwrap();
+                        (node.left, node.right, node.room) = (None, None, Some(cell));
+                        continue;
+                    }
+
+                    frontier.push((left, left_cell));
+                    frontier.push((right, right_cell));
+                }
+                _ => self.nodes[idx].as_mut().unwrap().room = Some(cell),
+            }
+        }
+
+        let rooms: Vec<PlacedRoom> = self
+            .get_subtree_rooms(0)
+            .iter()
+            .map(|node| (node.node_id, node.room.unwrap()))
+            .collect();
+        if rooms.is_empty() {
+            return Err(TreeError::NoLeavesError);
+        }
+
+        let mut joined: HashSet<(usize, usize)> = HashSet::new();
+
+        let parents: Vec<(usize, usize)> = self
+            .nodes
+            .iter()
+            .filter_map(|node| *node)
+            .filter_map(|node| Some((node.left?, node.right?)))
+            .collect();
+
+        for (left, right) in parents {
+            let right_rooms = self.get_subtree_rooms(right);
+            let mut doors: Vec<(usize, usize, (i32, i32))> = Vec::new();
+
+            for a in self.get_subtree_rooms(left) {
+                for b in right_rooms.iter() {
+                    for cell in DungeonTree::shared_wall(a.room.unwrap(), b.room.unwrap(), &rooms) {
+                        doors.push((a.node_id, b.node_id, cell));
+                    }
+                }
+            }
+
+            if let Some((a, b, cell)) = doors.choose(&mut rng) {
+                self.add_door(*a, *b, *cell);
+                joined.insert((*a, *b));
+            }
+        }
+
+        for (i, (a, room_a)) in rooms.iter().enumerate() {
+            for (b, room_b) in rooms.iter().skip(i + 1) {
+                if joined.contains(&(*a, *b)) || joined.contains(&(*b, *a)) || rng.gen::<f32>() >= settings.extra_doors {
+                    continue;
+                }
+
+                if let Some(cell) = DungeonTree::shared_wall(*room_a, *room_b, &rooms).choose(&mut rng) {
+                    self.add_door(*a, *b, *cell);
+                }
+            }
+        }
+
+        if settings.entrance {
+            let (x1, y1, x2, y2) = root.coords.unwrap();
+            let on_edge = |cell: &(i32, i32)| cell.0 == x1 || cell.1 == y1 || cell.0 == x2 - 1 || cell.1 == y2 - 1;
+
+            let outside: Vec<(i32, i32)> = rooms
+                .iter()
+                .flat_map(|(_, room)| DungeonTree::wall_cells(*room))
+                .filter(on_edge)
+                .filter(|cell| rooms.iter().filter(|(_, room)| rect_contains(*room, *cell)).count() == 1)
+                .collect();
+
+            if let Some(cell) = outside.choose(&mut rng) {
+                self.paths.push(DungeonPath::from_cells(&[*cell]));
+            }
+        }
+
+        self.update_room_graph();
+
+        Ok(())
+    }
+
+    fn add_door(&mut self, a: usize, b: usize, cell: (i32, i32)) {
+        let mut door = DungeonPath::from_cells(&[cell]);
+        door.rooms = Some((a, b));
+        self.paths.push(door);
+    }
+
+    //Cells of the wall a and b share, leaving out both rooms' corners and anywhere a third room's wall meets it
+    fn shared_wall(a: (i32, i32, i32, i32), b: (i32, i32, i32, i32), rooms: &[PlacedRoom]) -> Vec<(i32, i32)> {
+        let span = |low: i32, high: i32| low + 1..high - 1;
+
+        let cells: Vec<(i32, i32)> = if a.2 - 1 == b.0 || b.2 - 1 == a.0 {
+            let x = i32::max(a.0, b.0);
+            span(i32::max(a.1, b.1), i32::min(a.3, b.3)).map(|y| (x, y)).collect()
+        } else if a.3 - 1 == b.1 || b.3 - 1 == a.1 {
+            let y = i32::max(a.1, b.1);
+            span(i32::max(a.0, b.0), i32::min(a.2, b.2)).map(|x| (x, y)).collect()
+        } else {
+            Vec::new()
+        };
+
+        cells
+            .into_iter()
+            .filter(|cell| rooms.iter().filter(|(_, room)| rect_contains(*room, *cell)).count() == 2)
+            .collect()
+    }
+
+    //Wall cells of the room, corners left out
+    fn wall_cells(room: (i32, i32, i32, i32)) -> impl Iterator<Item = (i32, i32)> {
+        let (x1, y1, x2, y2) = room;
+
+        (x1 + 1..x2 - 1)
+            .flat_map(move |x| [(x, y1), (x, y2 - 1)])
+            .chain((y1 + 1..y2 - 1).flat_map(move |y| [(x1, y), (x2 - 1, y)]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FloorplanSettings;
+    use crate::map::rect_contains;
+    use crate::{split_dirs, Dungeon, DungeonTree};
+
+    #[test]
+    fn rooms_share_walls_and_open_onto_each_other_through_doors() {
+        for seed in 0..20 {
+            let settings = FloorplanSettings {
+                entrance: seed % 2 == 0,
+                ..FloorplanSettings::default()
+            };
+            let bounds = (0, 0, 60, 40);
+            let mut dungeon = Dungeon::new(bounds, 4, split_dirs::RANDOM);
+            dungeon.tree.set_seed(seed);
+            dungeon.generate_floorplan(&settings).unwrap();
+
+            let rooms: Vec<(usize, (i32, i32, i32, i32))> = dungeon.tree.get_subtree_rooms(0).iter().map(|node| (node.node_id, node.room.unwrap())).collect();
+            assert!(rooms.len() > 1 && rooms.len() <= 16);
+
+            //Rooms cover the whole root, and two rooms only ever overlap along a shared wall
+            for y in bounds.1..bounds.3 {
+                for x in bounds.0..bounds.2 {
+                    assert!(rooms.iter().any(|(_, room)| rect_contains(*room, (x, y))), "{:?} in no room", (x, y));
+                }
+            }
+            for (i, (_, a)) in rooms.iter().enumerate() {
+                for (_, b) in rooms[i + 1..].iter() {
+                    let overlap = (i32::min(a.2, b.2) - i32::max(a.0, b.0), i32::min(a.3, b.3) - i32::max(a.1, b.1));
+                    assert!(overlap.0 <= 1 || overlap.1 <= 1, "{:?} and {:?} overlap by {:?}", a, b, overlap);
+                }
+            }
+
+            //Interior doors are in the wall shared by the two rooms they join, and only the entrance is in the outer wall
+            let mut entrances = 0;
+            for path in dungeon.tree.paths() {
+                let door = path.start().unwrap();
+                let holding: Vec<usize> = rooms.iter().filter(|(_, room)| rect_contains(*room, door)).map(|(id, _)| *id).collect();
+                match path.rooms {
+                    Some((a, b)) => {
+                        assert_eq!(holding.len(), 2, "door {:?} isn't in a shared wall", door);
+                        assert!(holding.contains(&a) && holding.contains(&b));
+                    }
+                    None => {
+                        assert_eq!(holding.len(), 1);
+                        assert!(door.0 == 0 || door.1 == 0 || door.0 == 59 || door.1 == 39);
+                        entrances += 1;
+                    }
+                }
+            }
+            assert_eq!(entrances, settings.entrance as usize);
+
+            let map = dungeon.tree.rasterize();
+            let distances = map.distances_from(DungeonTree::room_center(rooms[0].1));
+            for (_, room) in rooms.iter() {
+                let center = DungeonTree::room_center(*room);
+                assert!(distances[map.index(center).unwrap()].is_some(), "{:?} can't be reached\n{}", room, map.to_ascii());
+            }
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::agents::{DrunkardGenerator, TunnelerGenerator};
+use crate::cave::CaveGenerator;
+use crate::floorplan::FloorplanSettings;
+use crate::hybrid::{LeafEntry, LeafStyle, LeafTable};
+use crate::map::DungeonMap;
+use crate::mask::Mask;
+use crate::maze::MazeSettings;
+use crate::population::{Spawn, SpawnTable};
+use crate::room_graph::RoomGraph;
+use crate::scatter::ScatterSettings;
+use crate::voronoi::VoronoiGenerator;
+use crate::wfc::WfcGenerator;
+use crate::{split_dirs, Dungeon, DungeonPath, DungeonTree, TreeError};
+
+//Anything that can lay out a map. Parameters live on the implementing type, and the regions on the map
+//are the rooms, caves or other areas it made.
+pub trait Generator {
+    //Generators seeded from seed give the same map for the same seed and bounds
+    fn generate(&self, bounds: (i32, i32, i32, i32), seed: u64) -> Result<Generated, TreeError>;
+}
+
+//A map along with how it was laid out and what was placed in it. Generators that don't dig corridors between rooms,
+//like caves, leave paths and room_graph empty, and only ones given a spawn table place spawns.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Generated {
+    pub map: DungeonMap,
+    pub paths: Vec<DungeonPath>,
+    //Rooms by their region id on the map
+    pub room_graph: RoomGraph,
+    pub spawns: Vec<Spawn>,
+}
+
+impl From<DungeonMap> for Generated {
+    fn from(map: DungeonMap) -> Generated {
+        Generated {
+            map,
+            paths: Vec::new(),
+            room_graph: RoomGraph::default(),
+            spawns: Vec::new(),
+        }
+    }
+}
+
+impl From<&DungeonTree> for Generated {
+    fn from(tree: &DungeonTree) -> Generated {
+        Generated {
+            map: tree.rasterize(),
+            paths: tree.paths().to_vec(),
+            room_graph: tree.room_graph().clone(),
+            spawns: tree.spawns().to_vec(),
+        }
+    }
+}
+
+//Places spawns from the table, if there is one, once the entrance and exit are in so rooms have their roles
+//and tiles their danger. A single map is always the first floor.
+fn populate(tree: &mut DungeonTree, table: &Option<SpawnTable>, seed: u64) -> Result<(), TreeError> {
+    if let Some(table) = table {
+        let _ = tree.place_entrance_exit(false);
+        tree.populate(table, 0, seed)?;
+    }
+
+    Ok(())
+}
+
+//What the BSP pipeline does with the tree once it's split
+#[derive(Clone, Debug, PartialEq)]
+pub enum BspMode {
+    //Rooms inset by the offsets and joined by gen_paths
+    Corridors,
+    //Rooms with interiors picked from the table
+    Hybrid(LeafTable),
+    //Rooms with mazes filling the space between them
+    RoomsAndMazes(MazeSettings),
+    //Rooms filling their sub-dungeons with shared walls
+    Floorplan(FloorplanSettings),
+}
+
+//Splits the bounds into a DungeonTree and rasterizes it
+#[derive(Clone, Debug, PartialEq)]
+pub struct BspGenerator {
+    pub splits: i64,
+    pub split_direction: split_dirs,
+    pub offsets: (i32, i32, i32, i32),
+    pub mode: BspMode,
+    pub mask: Option<Mask>,
+    pub spawns: Option<SpawnTable>,
+}
+
+impl Default for BspGenerator {
+    fn default() -> BspGenerator {
+        BspGenerator {
+            splits: 4,
+            split_direction: split_dirs::RANDOM,
+            offsets: (2, 2, 2, 2),
+            mode: BspMode::Corridors,
+            mask: None,
+            spawns: None,
+        }
+    }
+}
+
+impl Generator for BspGenerator {
+    fn generate(&self, bounds: (i32, i32, i32, i32), seed: u64) -> Result<Generated, TreeError> {
+        let mut dungeon = Dungeon::new(bounds, self.splits, self.split_direction);
+        dungeon.tree.set_seed(seed);
+        dungeon.tree.set_mask(self.mask.clone());
+
+        match &self.mode {
+            BspMode::Corridors => dungeon.generate(self.offsets)?,
+            BspMode::Hybrid(table) => dungeon.generate_hybrid(self.offsets, table)?,
+            BspMode::RoomsAndMazes(settings) => dungeon.generate_rooms_and_mazes(self.offsets, settings)?,
+            BspMode::Floorplan(settings) => dungeon.generate_floorplan(settings)?,
+        }
+        populate(&mut dungeon.tree, &self.spawns, seed)?;
+
+        Ok(Generated::from(&dungeon.tree))
+    }
+}
+
+//Scattered rooms from generate_scattered
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScatterGenerator {
+    pub settings: ScatterSettings,
+    pub mask: Option<Mask>,
+    pub spawns: Option<SpawnTable>,
+}
+
+impl Generator for ScatterGenerator {
+    fn generate(&self, bounds: (i32, i32, i32, i32), seed: u64) -> Result<Generated, TreeError> {
+        let mut dungeon = Dungeon::new(bounds, 0, split_dirs::RANDOM);
+        dungeon.tree.set_seed(seed);
+        dungeon.tree.set_mask(self.mask.clone());
+        dungeon.generate_scattered(&self.settings)?;
+        populate(&mut dungeon.tree, &self.spawns, seed)?;
+
+        Ok(Generated::from(&dungeon.tree))
+    }
+}
+
+impl Generator for CaveGenerator {
+    fn generate(&self, bounds: (i32, i32, i32, i32), seed: u64) -> Result<Generated, TreeError> {
+        CaveGenerator::generate(self, bounds, seed).map(Generated::from)
+    }
+}
+
+impl Generator for DrunkardGenerator {
+    fn generate(&self, bounds: (i32, i32, i32, i32), seed: u64) -> Result<Generated, TreeError> {
+        DrunkardGenerator::generate(self, bounds, seed).map(Generated::from)
+    }
+}
+
+impl Generator for TunnelerGenerator {
+    fn generate(&self, bounds: (i32, i32, i32, i32), seed: u64) -> Result<Generated, TreeError> {
+        TunnelerGenerator::generate(self, bounds, seed).map(Generated::from)
+    }
+}
+
+impl Generator for WfcGenerator {
+    fn generate(&self, bounds: (i32, i32, i32, i32), seed: u64) -> Result<Generated, TreeError> {
+        WfcGenerator::generate(self, bounds, seed).map(Generated::from)
+    }
+}
+
+impl Generator for VoronoiGenerator {
+    fn generate(&self, bounds: (i32, i32, i32, i32), seed: u64) -> Result<Generated, TreeError> {
+        let dungeon = self.generate_dungeon(bounds, seed)?;
+
+        Ok(Generated {
+            map: dungeon.map,
+            paths: dungeon.paths,
+            room_graph: dungeon.room_graph,
+            spawns: Vec::new(),
+        })
+    }
+}
+
+//Generators by name, kept in the order they were registered
+#[derive(Default)]
+pub struct GeneratorRegistry {
+    generators: Vec<(String, Box<dyn Generator>)>,
+}
+
+impl GeneratorRegistry {
+    //Every generator this crate has, with default parameters. WFC learns from its small built in sample.
+    pub fn with_builtins() -> GeneratorRegistry {
+        GeneratorRegistry::builtins(None)
+    }
+
+    //The builtins, with the ones that build a tree placing spawns from table
+    pub fn with_populated_builtins(table: &SpawnTable) -> GeneratorRegistry {
+        GeneratorRegistry::builtins(Some(table.clone()))
+    }
+
+    fn builtins(spawns: Option<SpawnTable>) -> GeneratorRegistry {
+        let mut registry = GeneratorRegistry::default();
+        let bsp = BspGenerator { spawns: spawns.clone(), ..BspGenerator::default() };
+
+        let table = LeafTable {
+            entries: vec![
+                LeafEntry { style: LeafStyle::Room, weight: 3 },
+                LeafEntry { style: LeafStyle::Cave(CaveGenerator::default()), weight: 1 },
+                LeafEntry { style: LeafStyle::Maze, weight: 1 },
+            ],
+        };
+
+        registry.register("bsp", bsp.clone());
+        registry.register("hybrid", BspGenerator { mode: BspMode::Hybrid(table), ..bsp.clone() });
+        registry.register(
+            "rooms-and-mazes",
+            BspGenerator { mode: BspMode::RoomsAndMazes(MazeSettings::default()), ..bsp.clone() },
+        );
+        registry.register(
+            "floorplan",
+            BspGenerator { mode: BspMode::Floorplan(FloorplanSettings::default()), ..bsp },
+        );
+        registry.register("scatter", ScatterGenerator { spawns, ..ScatterGenerator::default() });
+        registry.register("voronoi", VoronoiGenerator::default());
+        registry.register("cave", CaveGenerator::default());
+        registry.register("drunkard", DrunkardGenerator::default());
+        registry.register("tunneler", TunnelerGenerator::default());
+        registry.register("wfc", WfcGenerator::default());
+
+        registry
+    }
+
+    //Adds a generator under name, replacing whatever was there
+    pub fn register(&mut self, name: &str, generator: impl Generator + 'static) {
+        let generator: Box<dyn Generator> = Box::new(generator);
+
+        match self.generators.iter_mut().find(|(existing, _)| existing == name) {
+            Some(entry) => entry.1 = generator,
+            None => self.generators.push((name.to_string(), generator)),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Generator> {
+        self.generators.iter().find(|(existing, _)| existing == name).map(|(_, generator)| generator.as_ref())
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.generators.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    pub fn generate(&self, name: &str, bounds: (i32, i32, i32, i32), seed: u64) -> Result<Generated, TreeError> {
+        self.get(name)
+            .ok_or_else(|| TreeError::UnknownGeneratorError(name.to_string()))?
+            .generate(bounds, seed)
+    }
+}
+
+//Settings read from a config file of "key = value" lines, with blank lines and ones starting with # skipped.
+//Anything left out is None so the caller's own defaults or flags can fill it in.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GeneratorConfig {
+    //Name in the registry
+    pub generator: Option<String>,
+    pub seed: Option<u64>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    //File for WFC to learn from instead of its built in sample
+    pub sample: Option<PathBuf>,
+    //Spawn table file for the generators that build a tree to place spawns from
+    pub spawns: Option<PathBuf>,
+}
+
+impl GeneratorConfig {
+    pub fn parse(text: &str) -> Result<GeneratorConfig, TreeError> {
+        let mut config = GeneratorConfig::default();
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let bad = || TreeError::ConfigError(line.to_string());
+            let (key, value) = line.split_once('=').ok_or_else(bad)?;
+            let value = value.trim();
+
+            match key.trim() {
+                "generator" => config.generator = Some(value.to_string()),
+                "seed" => config.seed = Some(value.parse().map_err(|_| bad())?),
+                "width" => config.width = Some(value.parse().map_err(|_| bad())?),
+                "height" => config.height = Some(value.parse().map_err(|_| bad())?),
+                "sample" => config.sample = Some(PathBuf::from(value)),
+                "spawns" => config.spawns = Some(PathBuf::from(value)),
+                _ => return Err(bad()),
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn load(path: &Path) -> Result<GeneratorConfig, TreeError> {
+        let text = fs::read_to_string(path).map_err(|err| TreeError::ConfigReadError(err.to_string()))?;
+
+        GeneratorConfig::parse(&text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GeneratorConfig, GeneratorRegistry};
+    use crate::TreeError;
+
+    #[test]
+    fn builtins_give_the_same_map_for_the_same_seed() {
+        let registry = GeneratorRegistry::with_builtins();
+
+        for name in registry.names() {
+            //Wave function collapse is slow, so it gets a smaller map
+            let bounds = if name == "wfc" { (0, 0, 12, 10) } else { (0, 0, 40, 24) };
+            let mut made = 0;
+            for seed in 0..2 {
+                let first = registry.generate(name, bounds, seed);
+                let second = registry.generate(name, bounds, seed);
+                made += first.is_ok() as usize;
+                assert_eq!(first.ok(), second.ok(), "{} with seed {}", name, seed);
+            }
+            assert!(made > 0, "{} never made a map", name);
+        }
+    }
+
+    #[test]
+    fn configs_read_every_key_and_reject_anything_else() {
+        let config = GeneratorConfig::parse("# a cave\ngenerator = cave\n\nseed=7\n width = 60 \nheight = 40\nsample = rooms.txt\nspawns = goblins.txt\n").unwrap();
+        assert_eq!(config.generator.as_deref(), Some("cave"));
+        assert_eq!((config.seed, config.width, config.height), (Some(7), Some(60), Some(40)));
+        assert_eq!(config.sample.as_deref(), Some(std::path::Path::new("rooms.txt")));
+        assert_eq!(config.spawns.as_deref(), Some(std::path::Path::new("goblins.txt")));
+
+        for bad in ["colour = red", "seed = -1", "width"] {
+            assert!(matches!(GeneratorConfig::parse(bad), Err(TreeError::ConfigError(_))), "{:?} was accepted", bad);
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::cave::CaveGenerator;
+use crate::corridor::CorridorStyle;
+use crate::map::{DungeonMap, Tile};
+use crate::{Dungeon, DungeonTree, TreeError};
+
+//How the inside of a leaf's room is built
+#[derive(Clone, Debug, PartialEq)]
+pub enum LeafStyle {
+    //Plain rectangle from build_rooms
+    Room,
+    //Cellular automata cave filling the room, or a plain room if it comes out solid rock
+    Cave(CaveGenerator),
+    //Maze of one tile passages filling the room
+    Maze,
+    //Fixed layout drawn with the characters DungeonMap::to_ascii uses. The room shrinks to fit it
+    //plus a wall, and falls back to a plain room if it doesn't fit.
+    Prefab(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LeafEntry {
+    pub style: LeafStyle,
+    pub weight: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LeafTable {
+    pub entries: Vec<LeafEntry>,
+}
+
+impl Dungeon {
+    //Like generate, with each room's interior built from the table before the paths are dug
+    pub fn generate_hybrid(&mut self, offsets: (i32, i32, i32, i32), table: &LeafTable) -> Result<(), TreeError> {
+        self.split_all(offsets)?;
+        self.tree.build_rooms(offsets)?;
+        self.tree.build_interiors(table)?;
+        self.tree.gen_paths()
+    }
+}
+
+impl DungeonTree {
+    //Picks a style from the table for every room with floor in it and builds its interior.
+    //Interiors always have the room's center open and joined to the rest of their open space,
+    //so gen_paths can be run after as usual. build_rooms must have been run first.
+    pub fn build_interiors(&mut self, table: &LeafTable) -> Result<(), TreeError> {
+        let mut rng = self.fork_rng();
+        self.interiors.clear();
+
+        for node in self.get_subtree_rooms(0) {
+            let entry = match table.entries.choose_weighted(&mut rng, |entry| entry.weight) {
+                Ok(entry) => entry,
+                Err(_) => return Ok(()),
+            };
+            let room = node.room.unwrap();
+
+            let interior = match &entry.style {
+                LeafStyle::Room => None,
+                //Rooms too small for the cave to open up stay plain
+                LeafStyle::Cave(cave) => match cave.generate(room, rng.gen()) {
+                    Ok(interior) => Some(interior),
+                    Err(TreeError::EmptyMapError) => None,
+                    Err(err) => return Err(err),
+                },
+                LeafStyle::Maze => Some(DungeonTree::maze_interior(room, &mut rng)),
+                LeafStyle::Prefab(prefab) => match DungeonTree::prefab_interior(room, prefab)? {
+                    Some(interior) => {
+                        self.nodes[node.node_id].as_mut().unwrap().room = Some(interior.bounds);
+                        Some(interior)
+                    }
+                    None => None,
+                },
+            };
+
+            if let Some(mut interior) = interior {
+                DungeonTree::open_center(&mut interior);
+                self.interiors.insert(node.node_id, interior);
+            }
+        }
+
+        Ok(())
+    }
+
+    //Maze over every other tile inside the room's wall, turning at random
+    fn maze_interior<R: Rng>(room: (i32, i32, i32, i32), rng: &mut R) -> DungeonMap {
+        let mut map = DungeonMap::new(room);
+        let inside = |point: (i32, i32)| point.0 > room.0 && point.0 < room.2 - 1 && point.1 > room.1 && point.1 < room.3 - 1;
+
+        map.grow_maze((room.0 + 1, room.1 + 1), Tile::Floor, 0.0, inside, rng);
+
+        map
+    }
+
+    //The prefab centered in the room, with the room cut down to it plus a wall. None if it doesn't fit.
+    fn prefab_interior(room: (i32, i32, i32, i32), prefab: &str) -> Result<Option<DungeonMap>, TreeError> {
+        let rows: Vec<Vec<Tile>> = prefab
+            .lines()
+            .map(|line| line.chars().map(|c| Tile::from_char(c).ok_or(TreeError::UnknownTileError(c))).collect())
+            .collect::<Result<_, _>>()?;
+
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0) as i32 + 2;
+        let height = rows.len() as i32 + 2;
+        if width > room.2 - room.0 || height > room.3 - room.1 {
+            return Ok(None);
+        }
+
+        let x1 = room.0 + (room.2 - room.0 - width) / 2;
+        let y1 = room.1 + (room.3 - room.1 - height) / 2;
+        let mut map = DungeonMap::new((x1, y1, x1 + width, y1 + height));
+
+        for (y, row) in rows.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                map.set((x1 + 1 + x as i32, y1 + 1 + y as i32), *tile);
+            }
+        }
+
+        Ok(Some(map))
+    }
+
+    //Digs from the center to the nearest open tile if it isn't open already,
+    //then fills in anything not joined to the center and redraws the walls around what's left
+    fn open_center(interior: &mut DungeonMap) {
+        let center = DungeonTree::room_center(interior.bounds);
+
+        if !interior.is_walkable(center) {
+            let nearest = (0..interior.len())
+                .map(|idx| interior.point(idx))
+                .filter(|point| interior.is_walkable(*point))
+                .min_by_key(|point| (point.0 - center.0).abs() + (point.1 - center.1).abs());
+
+            let mut points = vec![center];
+            if let Some(nearest) = nearest {
+                CorridorStyle::push_leg(&mut points, nearest);
+            }
+            for point in points {
+                if !interior.is_walkable(point) {
+                    interior.set(point, Tile::Floor);
+                }
+            }
+        }
+
+        for (idx, dist) in interior.distances_from(center).into_iter().enumerate() {
+            let point = interior.point(idx);
+            if interior.get(point) == Some(Tile::Wall) || (interior.is_walkable(point) && dist.is_none()) {
+                interior.set(point, Tile::Empty);
+            }
+        }
+        interior.wall_in();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LeafEntry, LeafStyle, LeafTable};
+    use crate::cave::CaveGenerator;
+    use crate::map::{rect_contains, Tile};
+    use crate::{split_dirs, Dungeon, DungeonTree};
+
+    #[test]
+    fn interiors_stay_inside_their_leaves() {
+        let styles = [
+            LeafStyle::Room,
+            LeafStyle::Cave(CaveGenerator::default()),
+            LeafStyle::Maze,
+            LeafStyle::Prefab("...\n.#.\n...".to_string()),
+        ];
+        let table = LeafTable {
+            entries: styles.into_iter().map(|style| LeafEntry { style, weight: 1 }).collect(),
+        };
+
+        let mut built = 0;
+
+        for seed in 0..20 {
+            let mut dungeon = Dungeon::new((0, 0, 80, 60), 4, split_dirs::RANDOM);
+            dungeon.tree.set_seed(seed);
+            dungeon.generate_hybrid((2, 2, 2, 2), &table).unwrap();
+            let map = dungeon.tree.rasterize();
+            built += dungeon.tree.interiors.len();
+
+            for (node_id, interior) in dungeon.tree.interiors.iter() {
+                let node = dungeon.tree.nodes[*node_id].unwrap();
+                let (room, coords) = (node.room.unwrap(), node.coords.unwrap());
+                assert!(room.0 >= coords.0 && room.1 >= coords.1 && room.2 <= coords.2 && room.3 <= coords.3);
+
+                for idx in (0..interior.len()).filter(|idx| interior.get(interior.point(*idx)) != Some(Tile::Empty)) {
+                    let point = interior.point(idx);
+                    assert!(rect_contains(room, point), "room {} built outside {:?} at {:?}", node_id, room, point);
+                }
+            }
+
+            //Every room's center is open and gen_paths reached it through whatever was built inside
+            let centers: Vec<(i32, i32)> = dungeon.tree.get_subtree_rooms(0).iter().map(|node| DungeonTree::room_center(node.room.unwrap())).collect();
+            let distances = map.distances_from(centers[0]);
+            for center in centers {
+                assert!(distances[map.index(center).unwrap()].is_some(), "{:?} can't be reached\n{}", center, map.to_ascii());
+            }
+        }
+
+        assert!(built > 0);
+    }
+}
@@ -0,0 +1,1211 @@
+use crossterm::{
+    cursor,
+    style::{self, Stylize},
+    terminal,
+    ExecutableCommand, QueueableCommand,
+};
+use ptree::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::{fmt::Display};
+use std::path::Path;
+use std::{borrow::Cow, fs::File};
+use std::collections::HashMap;
+use std::{
+    io::{self, Write},
+    task::Poll,
+};
+use thiserror::Error;
+
+pub mod agents;
+pub mod cave;
+pub mod corridor;
+pub mod danger;
+pub mod dead_ends;
+pub mod entrance;
+pub mod floorplan;
+pub mod generator;
+pub mod hybrid;
+pub mod lock_key;
+pub mod loops;
+pub mod map;
+pub mod mask;
+pub mod maze;
+pub mod multi_level;
+pub mod path;
+pub mod planner;
+pub mod population;
+pub mod room_graph;
+pub mod router;
+pub mod scatter;
+pub mod voronoi;
+pub mod wfc;
+
+use corridor::CorridorStyle;
+use entrance::EntranceExit;
+use lock_key::Progression;
+use map::DungeonMap;
+use mask::Mask;
+use path::Segment;
+use population::Spawn;
+use room_graph::RoomGraph;
+use router::RouteCosts;
+
+//use display_tree::*;
+
+pub struct Dungeon {
+    tree: DungeonTree,
+    homogeneity: f64, //Will be clamped between 0 and 1. Increases the variance for the splitting of sub-dungeons.
+    splits: i64, //How many times the sub-dungeons will be split. Going to high with too small of a dungeon can produce odd results.
+    split_direction: split_dirs, // Split the sub-dungeons horziontally or vertically.
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum split_dirs {
+    ALWAYS_VERT,
+    ALWAYS_HORIZONTAL,
+    RANDOM,
+}
+
+impl Dungeon {
+    pub fn new(coords: (i32, i32, i32, i32), splits: i64, split_direction: split_dirs) -> Dungeon {
+        let mut tree = DungeonTree::new(splits as usize);
+        let _ = tree.setRoot(DungeonNode {
+            coords: Some(coords),
+            ..DungeonNode::new()
+        });
+
+        Dungeon {
+            tree,
+            homogeneity: 0.5,
+            splits,
+            split_direction,
+        }
+    }
+
+    //Splits every sub-dungeon `splits` levels deep, then builds the rooms and the paths between them.
+    //Sub-dungeons too narrow to split again are left as leaves.
+    pub fn generate(&mut self, offsets: (i32, i32, i32, i32)) -> Result<(), TreeError> {
+        self.split_all(offsets)?;
+        self.tree.build_rooms(offsets)?;
+        self.tree.gen_paths()
+    }
+
+    pub(crate) fn split_all(&mut self, offsets: (i32, i32, i32, i32)) -> Result<(), TreeError> {
+        let min_size = 2 * (offsets.0 + offsets.2 + 4);
+        let mut frontier: Vec<usize> = vec![0];
+
+        for _ in 0..self.splits {
+            let mut next: Vec<usize> = Vec::new();
+
+            for idx in frontier {
+                let coords = self.tree.nodes[idx].unwrap().coords.unwrap();
+
+                //With a mask the split is made across just the part of the sub-dungeon inside it
+                let clipped = match &self.tree.mask {
+                    Some(mask) => match mask.clip(coords) {
+                        Some(clipped) => clipped,
+                        None => continue,
+                    },
+                    None => coords,
+                };
+                let width = clipped.2 - clipped.0;
+                let height = clipped.3 - clipped.1;
+
+                let vert = match self.split_direction {
+                    split_dirs::ALWAYS_VERT => true,
+                    split_dirs::ALWAYS_HORIZONTAL => false,
+                    split_dirs::RANDOM => self.tree.rng.gen_bool(0.5),
+                };
+
+                if (vert && width < min_size) || (!vert && height < min_size) {
+                    continue;
+                }
+
+                self.tree.nodes[idx].as_mut().unwrap().coords = Some(clipped);
+                self.tree.split_sub_dungeon(vert, idx as i32)?;
+                self.tree.nodes[idx].as_mut().unwrap().coords = Some(coords);
+                next.push(2 * idx + 1);
+                next.push(2 * idx + 2);
+            }
+
+            frontier = next;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TreeError {
+    #[error("Tree already has root node... ")]
+    RootErr,
+
+    #[error("Invalid index for tree...")]
+    IndexError,
+
+    #[error("Can't split into sub-dungeons from dungeon of length or width smaller than three...")]
+    SubDungeonSplitError,
+
+    #[error("No leaves found in tree...")]
+    NoLeavesError,
+
+    #[error("You must provide a Some(room)...")]
+    RoomIsNoneError,
+
+    #[error("Not enough corridors between the start and exit to place the requested locks...")]
+    LockPlacementError,
+
+    #[error("Exit can't be reached with the keys available...")]
+    UnsolvableError,
+
+    #[error("No spot found inside rooms on both floors to place stairs...")]
+    StairPlacementError,
+
+    #[error("Not every room can be reached...")]
+    DisconnectedError,
+
+    #[error("No entrance has been placed...")]
+    NoEntranceError,
+
+    #[error("No route found between the two points...")]
+    NoRouteError,
+
+    #[error("No open space left in the map...")]
+    EmptyMapError,
+
+    #[error("Sample has a character that isn't a tile: {0:?}...")]
+    UnknownTileError(char),
+
+    #[error("Sample or map is smaller than the {0}x{0} patterns...")]
+    PatternSizeError(usize),
+
+    #[error("Wave function collapse ran into a contradiction it couldn't back out of after {0} retries...")]
+    ContradictionError(usize),
+
+    #[error("No generator registered as {0:?}...")]
+    UnknownGeneratorError(String),
+
+    #[error("Mask isn't a plain or raw PBM image...")]
+    MaskFormatError,
+
+    #[error("Couldn't read the mask: {0}...")]
+    MaskReadError(String),
+
+    #[error("Couldn't read the sample: {0}...")]
+    SampleReadError(String),
+
+    #[error("Couldn't read the config: {0}...")]
+    ConfigReadError(String),
+
+    #[error("Couldn't read the spawn table: {0}...")]
+    SpawnTableReadError(String),
+
+    #[error("Spawn table line isn't an entry or a density: {0:?}...")]
+    SpawnTableError(String),
+
+    #[error("Config line isn't a known \"key = value\": {0:?}...")]
+    ConfigError(String),
+}
+
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct DungeonNode {
+    //x1, y1, x2, y2
+    coords: Option<(i32, i32, i32, i32)>,
+    node_id: usize,
+    left: Option<usize>,
+    right: Option<usize>,
+    room: Option<(i32, i32, i32, i32)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DungeonPath
+{
+    //In the order the path is walked
+    segments: Vec<Segment>,
+    //node_id of the two rooms this path joins, if it was built between rooms
+    rooms: Option<(usize, usize)>,
+    //How many tiles across the corridor is dug
+    width: i32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DungeonTree {
+    nodes: Vec<Option<DungeonNode>>,
+    paths: Vec<DungeonPath>,
+    progression: Option<Progression>,
+    entrance_exit: Option<EntranceExit>,
+    spawns: Vec<Spawn>,
+    corridor_style: CorridorStyle,
+    //Styles for particular connections, keyed by (lower node_id, higher node_id)
+    connection_styles: HashMap<(usize, usize), CorridorStyle>,
+    corridor_width: i32,
+    //Widths for particular connections, keyed like connection_styles
+    connection_widths: HashMap<(usize, usize), i32>,
+    //Tips of the spurs dug by add_dead_ends
+    dead_ends: Vec<(i32, i32)>,
+    //Which rooms each path joins, kept up to date as paths change
+    room_graph: RoomGraph,
+    pass_through_doors: bool,
+    //Tiles inside the rooms build_interiors didn't leave as plain rectangles, keyed by node_id
+    interiors: HashMap<usize, DungeonMap>,
+    //Tiles the dungeon has to stay inside, None for the whole root
+    mask: Option<Mask>,
+    //Everything random done to the tree draws from this, so the same seed gives the same dungeon
+    rng: StdRng,
+}
+
+impl TreeItem for DungeonTree {
+    type Child = Self;
+    fn write_self<W: io::Write>(&self, f: &mut W, style: &Style) -> io::Result<()> {
+        write!(f, "{}", style.paint(self))
+    }
+    fn children(&self) -> Cow<[Self::Child]> {
+        let left_subtree = self.get_subtree(0, true);
+        let right_subtree = self.get_subtree(0, false);
+
+        if (left_subtree != None) && (right_subtree != None) {
+            return Cow::from(vec![left_subtree.unwrap(), right_subtree.unwrap()]);
+        }
+
+        if (left_subtree != None) && (right_subtree == None) {
+            return Cow::from(vec![left_subtree.unwrap()]);
+        }
+
+        if (left_subtree == None) && (right_subtree != None) {
+            return Cow::from(vec![right_subtree.unwrap()]);
+        }
+
+        Cow::from(vec![])
+    }
+}
+
+impl std::fmt::Display for DungeonTree {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        // if (self.nodes.len() > 0) {
+        //     write!(fmt, "Coords: {:?}", self.nodes[0].unwrap().coords)?;
+        //     if (self.nodes[0].unwrap().room != None) {
+        //         write!(fmt, "Room: {:?}", self.nodes[0].unwrap().room.unwrap())
+        //     } else {
+        //         write!(fmt, "Room: {:?}", None::<DungeonNode>)
+        //     }
+        // } else {
+        //     write!(fmt, "None")
+        // }
+        write!(fmt, "Coords: {:?}", self.nodes[0])
+    }
+}
+
+impl DungeonNode {
+    pub fn new() -> DungeonNode {
+        DungeonNode {
+            coords: None,
+            left: None,
+            right: None,
+            room: None,
+            node_id: 0,
+        }
+    }
+}
+
+impl DungeonTree {
+    //Return a empty tree with no nodes
+    pub fn new(splits: usize) -> DungeonTree {
+        DungeonTree {
+            nodes: Vec::with_capacity(splits * 2),
+            paths: Vec::new(),
+            progression: None,
+            entrance_exit: None,
+            spawns: Vec::new(),
+            corridor_style: CorridorStyle::LShape,
+            connection_styles: HashMap::new(),
+            corridor_width: 1,
+            connection_widths: HashMap::new(),
+            dead_ends: Vec::new(),
+            room_graph: RoomGraph::default(),
+            pass_through_doors: false,
+            interiors: HashMap::new(),
+            mask: None,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    //Reseeds the tree so everything generated from here on comes out the same for the same seed
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    //A generator of its own for one step, seeded from the tree's so it's just as repeatable
+    pub(crate) fn fork_rng(&mut self) -> StdRng {
+        StdRng::seed_from_u64(self.rng.gen())
+    }
+
+    // pub fn num_children(&self, root_node: DungeonNode) -> i32
+    // {
+    //     let num = 0;
+
+    //     let mut root_idx = root_node.node_id;
+    //     while()
+    // }
+
+
+    //Connects the two subtrees of every split node with a path between their closest pair of rooms.
+    //Every room ends up reachable from every other room, and each path records the rooms it joins.
+    pub fn gen_paths(& mut self) -> Result<(), TreeError>
+    {
+        let connections = self.get_connections()?;
+        self.connect_rooms(&connections)?;
+
+        Ok(())
+    }
+
+    //Digs a path between the centers of each pair of rooms (by node_id), shaped and sized by the corridor style and width set for that pair
+    //With a mask, paths are routed with A* so they stay inside it, and a pair with no way through inside it is a NoRouteError.
+    //Paths dug before that are kept.
+    pub fn connect_rooms(&mut self, pairs: &[(usize, usize)]) -> Result<(), TreeError>
+    {
+        let mut rng = self.fork_rng();
+        let mut routing = self.mask.as_ref().map(|_| self.routing_map());
+
+        for (left, right) in pairs.iter().copied()
+        {
+            if let Some(map) = routing.as_mut()
+            {
+                match self.route_path(map, left, right, &RouteCosts::default())
+                {
+                    Ok(pth) => {
+                        for point in pth.cells() {
+                            map.carve_brush(point, pth.width);
+                        }
+                        self.paths.push(pth);
+                    }
+                    Err(err) => {
+                        self.update_room_graph();
+                        return Err(err);
+                    }
+                }
+                continue;
+            }
+
+            let mut pth = self.get_connection_style(left, right).carve(
+                DungeonTree::room_center(self.nodes[left].unwrap().room.unwrap()),
+                DungeonTree::room_center(self.nodes[right].unwrap().room.unwrap()),
+                &mut rng,
+            );
+            pth.rooms = Some((left, right));
+            pth.width = self.get_connection_width(left, right);
+            self.paths.push(pth);
+        }
+
+        self.update_room_graph();
+
+        Ok(())
+    }
+
+    //Every corridor as dug, in the order they were made
+    pub fn paths(&self) -> &[DungeonPath] {
+        &self.paths
+    }
+
+    //node_id of the closest pair of rooms across the two subtrees of every split node
+    pub fn get_connections(&self) -> Result<Vec<(usize, usize)>, TreeError>
+    {
+        let parents: Vec<DungeonNode> = self.nodes.iter()
+        .flatten()
+        .copied()
+        .filter(|node| node.left.is_some() && node.right.is_some())
+        .collect();
+
+        if parents.is_empty()
+        {
+            return Err(TreeError::NoLeavesError);
+        }
+
+        let mut connections: Vec<(usize, usize)> = Vec::new();
+
+        for parent in parents
+        {
+            let left_rooms = self.get_subtree_rooms(parent.left.unwrap());
+            let right_rooms = self.get_subtree_rooms(parent.right.unwrap());
+
+            let mut closest: Option<(DungeonNode, DungeonNode, i32)> = None;
+
+            for left in left_rooms.iter()
+            {
+                for right in right_rooms.iter()
+                {
+                    let center_left = DungeonTree::room_center(left.room.unwrap());
+                    let center_right = DungeonTree::room_center(right.room.unwrap());
+                    let dist = (center_left.0 - center_right.0).abs() + (center_left.1 - center_right.1).abs();
+
+                    if closest.is_none() || dist < closest.unwrap().2
+                    {
+                        closest = Some((*left, *right, dist));
+                    }
+                }
+            }
+
+            if let Some((left, right, _)) = closest
+            {
+                connections.push((left.node_id, right.node_id));
+            }
+        }
+
+        Ok(connections)
+    }
+
+    //All leaves under (and including) the node at node_idx that have a room built with floor inside its walls
+    pub fn get_subtree_rooms(&self, node_idx: usize) -> Vec<DungeonNode>
+    {
+        let mut idxs: Vec<usize> = vec![node_idx];
+        let _ = self.get_children_idxs(self.nodes[node_idx], &mut idxs);
+
+        idxs.iter()
+        .filter_map(|idx| self.nodes[*idx])
+        .filter(|node| node.left.is_none() && node.right.is_none())
+        .filter(|node| node.room.is_some_and(DungeonTree::has_floor))
+        .collect()
+    }
+
+    pub fn has_floor(room: (i32, i32, i32, i32)) -> bool
+    {
+        room.2 - room.0 >= 3 && room.3 - room.1 >= 3
+    }
+
+    pub fn room_center(room: (i32, i32, i32, i32)) -> (i32, i32)
+    {
+        ((room.0 + room.2) / 2, (room.1 + room.3) / 2)
+    }
+
+    // fn is_within_range(point: (i32, i32), range:(i32,i32,i32,i32)) -> bool
+    // {
+    //     if((point.0 >= range.0) && (point.0 <= range.2)) || ((point.1 >= range.1) && (point.1 <= range.3))
+    //     {
+    //         return true;
+    //     }
+    //     false
+    // }
+
+    // fn get_direction_of_room(room_1: (i32,i32,i32,i32), room_2: (i32,i32,i32,i32)) -> rect_face
+    // {
+    //     let room_1_center: (i32,i32) = (room_1.2 / 2, room_1.3 / 2);
+    //     let room_2_center:(i32,i32)=  (room_2.2 / 2, room_2.3 / 2);
+
+    //     if(room_1_center.1 > room_2_center.1 && Self::is_within_range(room_1_center, room_2))
+    //     {
+    //         return rect_face::SOUTH;
+    //     }
+
+    //     return rect_face::EAST;
+    // }
+
+    // //Think of this as a line, with (start of line, end of line)
+    // fn get_face(room: Option<(i32,i32,i32,i32)>, face: rect_face) -> Result<Option<(i32,i32, i32, i32)>, TreeError>
+    // {
+    //     if(room == None)
+    //     {
+    //         return Err(TreeError::RoomIsNoneError);
+    //     }
+
+    //     match face{
+    //         rect_face::NORTH => return Ok(Some((room.unwrap().0, room.unwrap().1, room.unwrap().2, room.unwrap().1))),
+    //         rect_face::SOUTH => return Ok(Some((room.unwrap().0, room.unwrap().3, room.unwrap().2, room.unwrap().3))),
+    //         rect_face::EAST =>  return Ok(Some((room.unwrap().2, room.unwrap().1, room.unwrap().2, room.unwrap().3))),
+    //         rect_face::WEST =>  return Ok(Some((room.unwrap().0, room.unwrap().1, room.unwrap().0, room.unwrap().3))),
+    //         _ => return Ok(None)
+    //     }
+
+    // } 
+
+    // fn get_common_faces(room_1: Option<(i32,i32,i32,i32)>, room_2: Option<(i32,i32,i32,i32)>) -> 
+
+    // //Stupid name, but gets the range, that two rooms share on the x or y axis respectively
+    // fn get_face_range(room_1: Option<(i32,i32,i32,i32)>, room_2: Option<(i32,i32,i32,i32)>) -> Result<Option<(i32,i32,i32,i32)>, TreeError>
+    // {
+
+    //     let x_range: Option<(i32, i32)> = Some((0,0));
+    //     let y_range: Option<(i32, i32)> = Some((0,0));
+
+    //     if(room_1.is_none() || room_2.is_none())
+    //     {
+    //         return Err(TreeError::RoomIsNoneError);
+    //     }
+
+    //     let room_1_x_range: Option<(i32, i32)> = Some((room_1.unwrap().0,room_1.unwrap().2));
+    //     let room_1_y_range: Option<(i32, i32)> = Some((room_1.unwrap().1,room_1.unwrap().3));
+    //     let room_2_x_range: Option<(i32, i32)> = Some((room_2.unwrap().0,room_2.unwrap().2));
+    //     let room_2_y_range: Option<(i32, i32)> = Some((room_2.unwrap().1,room_2.unwrap().3));
+
+    //     let shares_x_points = 
+
+
+    //     else {
+    //         let leftmost_x: i32;
+    //         if(room_1.unwrap().0 < room_2.unwrap().0)
+    //         {
+
+    //         }
+    //     }
+
+        
+    //     Ok()
+    // }
+
+    //Sets the root of the tree
+    pub fn setRoot(&mut self, root_node: DungeonNode) -> Result<(), TreeError> {
+        if self.nodes.len() != 0 {
+            Err(TreeError::RootErr)
+        } else {
+            *self = DungeonTree {
+                nodes: vec![Some(root_node); 1],
+                paths: Vec::new(),
+                progression: None,
+                entrance_exit: None,
+                spawns: Vec::new(),
+                corridor_style: self.corridor_style,
+                connection_styles: self.connection_styles.clone(),
+                corridor_width: self.corridor_width,
+                connection_widths: self.connection_widths.clone(),
+                dead_ends: Vec::new(),
+                room_graph: RoomGraph::default(),
+                pass_through_doors: self.pass_through_doors,
+                interiors: HashMap::new(),
+                mask: self.mask.clone(),
+                rng: self.rng.clone(),
+            };
+            Ok(())
+        }
+    }
+
+    pub fn get_leaves(&self) -> Result<Vec<Option<DungeonNode>>, TreeError>
+    {
+        let leaves: Vec<Option<DungeonNode>> = self.nodes.iter()
+        .filter(|node| node.is_some())
+        .filter(|node| (node.unwrap().left == None) && (node.unwrap().right == None))
+        .map(|node| *node)
+        .collect();
+
+        if(leaves.len() <= 0)
+        {
+            Err(TreeError::NoLeavesError)
+        }
+        else {
+            Ok(leaves)
+        }
+    }
+
+    pub fn build_rooms(&mut self, offsets: (i32, i32, i32, i32)) -> Result<(), TreeError> {
+        let min_x_offset = offsets.0;
+        let min_y_offset = offsets.1;
+        let max_x_offset = offsets.2;
+        let max_y_offset = offsets.3;
+
+        //Only build rooms for leaves?
+        let mut itr: Vec<_> = self.nodes
+        .iter_mut()
+        .filter(|node| node.is_some())
+        .filter(|node| (node.unwrap().left.is_none()) && (node.unwrap().right.is_none())).collect();
+
+        for sub_dungeons in itr.iter_mut().enumerate() {
+            // if (sub_dungeons.0 == 0) {
+            //     sub_dungeons.1.unwrap().room = None;
+            //     continue;
+            // }
+
+            if (**sub_dungeons.1 != None) {
+                let sub_width = sub_dungeons.1.unwrap().coords.unwrap().2
+                    - sub_dungeons.1.unwrap().coords.unwrap().0;
+                let sub_height = sub_dungeons.1.unwrap().coords.unwrap().3
+                    - sub_dungeons.1.unwrap().coords.unwrap().1;
+
+                if (sub_width <= 3 || sub_height <= 3) {
+                    println!("Sub dungeon is too small!");
+                    sub_dungeons.1.unwrap().room = None;
+                    continue;
+                }
+
+                //Simplistic, randomize later
+                let dims = (
+                    sub_dungeons.1.unwrap().coords.unwrap().0 + min_x_offset,
+                    sub_dungeons.1.unwrap().coords.unwrap().1 + min_y_offset,
+                    sub_dungeons.1.unwrap().coords.unwrap().2 - max_x_offset,
+                    sub_dungeons.1.unwrap().coords.unwrap().3 - max_y_offset,
+                );
+
+                sub_dungeons.1.as_mut().unwrap().room = Some(dims);
+            }
+        }
+
+        self.fit_rooms_to_mask();
+        Ok(())
+    }
+
+    pub fn get_subtree(&self, node_idx: usize, left: bool) -> Option<DungeonTree> {
+        if node_idx >= self.nodes.len() {
+            return None;
+        }
+
+        let mut kids: Vec<usize> = Vec::new();
+
+        match self.nodes[node_idx] {
+            Some(node) => match left {
+                true => match node.left {
+                    Some(left_child_idx) => {
+                        //kids.push(node_idx);
+                        kids.push(left_child_idx);
+                        match self.get_children_idxs(self.nodes[left_child_idx], &mut kids) {
+                            Ok(_) => {
+                                let mut subtree: DungeonTree = DungeonTree::new(1);
+
+                                let mut new_node_id = 0;
+                                let mut new_child_id = 1;
+                                for idx in kids {
+                                    let mut node: DungeonNode = self.nodes[idx].unwrap().clone();
+                                    node.node_id = new_node_id;
+
+                                    if (node.left != None) {
+                                        node.left = Some(new_child_id);
+                                        new_child_id += 1;
+                                    }
+
+                                    if (node.right != None) {
+                                        node.right = Some(new_child_id);
+                                        new_child_id += 1;
+                                    }
+
+                                    subtree.nodes.push(Some(node));
+
+                                    new_node_id += 1;
+                                }
+
+                                return Some(subtree);
+                            }
+                            _ => {}
+                        }
+                    }
+                    None => {
+                        return None;
+                    }
+                },
+                false => {
+                    match node.right {
+                        Some(right_child_idx) => {
+                            //kids.push(node_idx);
+                            kids.push(right_child_idx);
+                            match self.get_children_idxs(self.nodes[right_child_idx], &mut kids) {
+                                Ok(_) => {
+                                    let mut subtree: DungeonTree = DungeonTree::new(1);
+
+                                    let mut new_node_id = 0;
+                                    let mut new_child_id = 1;
+                                    for idx in kids {
+                                        let mut node: DungeonNode =
+                                            self.nodes[idx].unwrap().clone();
+                                        node.node_id = new_node_id;
+
+                                        if (node.left != None) {
+                                            node.left = Some(new_child_id);
+                                            new_child_id += 1;
+                                        }
+
+                                        if (node.right != None) {
+                                            node.right = Some(new_child_id);
+                                            new_child_id += 1;
+                                        }
+
+                                        subtree.nodes.push(Some(node));
+
+                                        new_node_id += 1;
+                                    }
+
+                                    return Some(subtree);
+                                }
+                                _ => {}
+                            }
+                        }
+                        None => {
+                            return None;
+                        }
+                    }
+                }
+            },
+
+            None => {
+                return None;
+            }
+        }
+
+        None
+    }
+
+    pub fn get_children_idxs(
+        &self,
+        rt: Option<DungeonNode>,
+        child_idxs: &mut Vec<usize>,
+    ) -> Result<(), TreeError> {
+        if (rt == None) {
+            return Ok(());
+        }
+
+        if (rt.unwrap().left != None) {
+            child_idxs.push(rt.unwrap().left.unwrap());
+            self.get_children_idxs(self.nodes[rt.unwrap().left.unwrap()], child_idxs)?;
+        }
+
+        if (rt.unwrap().right != None) {
+            child_idxs.push(rt.unwrap().right.unwrap());
+            self.get_children_idxs(self.nodes[rt.unwrap().right.unwrap()], child_idxs)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn remove_at_idx(&mut self, node_idx: i32) {
+        let mut stk: Vec<usize> = Vec::new();
+        let mut toRemove: Vec<i32> = Vec::new();
+
+        let mut curr: Option<usize> = Some(node_idx as usize);
+
+        while (!stk.is_empty()) || (curr != None) {
+            match curr {
+                Some(_) => {
+                    //println!("{:?}", curr.unwrap());
+                    match self.nodes[curr.unwrap() as usize] {
+                        Some(node) => {
+                            stk.push(node.node_id);
+
+                            match node.left {
+                                Some(left_child_idx) => curr = Some(left_child_idx),
+                                None => curr = None,
+                            };
+                        }
+                        None => {}
+                    };
+                }
+                None => {
+                    curr = stk.last().copied();
+                    stk.pop();
+
+                    match self.nodes[curr.unwrap() as usize] {
+                        Some(node) => {
+                            toRemove.push(node.node_id as i32);
+
+                            match node.right {
+                                Some(right_child_idx) => curr = Some(right_child_idx),
+                                None => {
+                                    curr = None;
+                                }
+                            }
+                        }
+                        None => {}
+                    }
+                }
+            };
+
+            // if(curr != None)
+            // {
+            //     if(curr.unwrap() < self.nodes.len() as i32 && self.nodes[curr.unwrap() as usize] != None)
+            //     {
+            //         stk.push(self.nodes[curr.unwrap() as usize].unwrap().node_id as i32 & self.nodes.len() as i32);
+            //     }
+
+            //     if(self.nodes[curr.unwrap() as usize].unwrap().left == None)
+            //     {
+            //         curr = None;
+            //     }
+            //     else {
+            //         curr = Some(self.nodes[curr.unwrap() as usize].unwrap().left.unwrap() as i32);
+            //     }
+
+            // }
+
+            // else {
+            //     curr = stk.last().copied();
+            //     stk.pop();
+            //     //print!("{}", self.nodes[curr.unwrap() as usize].unwrap().node_id);
+            //     toRemove.push(self.nodes[curr.unwrap() as usize].unwrap().node_id as i32);
+            //     if(self.nodes[curr.unwrap() as usize].unwrap().right == None)
+            //     {
+            //         curr = None;
+            //     }
+            //     else {
+            //         curr = Some(self.nodes[curr.unwrap() as usize].unwrap().right.unwrap() as i32);
+            //     }
+            // }
+        }
+
+        for idx in toRemove {
+            self.nodes[idx as usize] = None;
+        }
+    }
+
+    //At the given node, split it into two sub-dungeons. If sub-dungeons already exist at the child node locations, they will be over-written.
+    //Be careful with this, as your DungeonTree node vector will continue to increase in size even if it isn't necessary.
+    pub fn split_sub_dungeon(&mut self, vert: bool, node_idx: i32) -> Result<(), TreeError> {
+        let mut split_pos: i32;
+        let mut split_range: (i32, i32);
+        let root_idx: usize;
+        let root_node: DungeonNode;
+
+        match self
+            .nodes
+            .iter_mut()
+            .enumerate()
+            .find(|c| c.0 == node_idx as usize)
+        {
+            Some((idx, node)) => {
+                root_node = node.unwrap().clone();
+                root_idx = idx;
+                node.as_mut().unwrap().left = Some(2 * idx + 1);
+                node.as_mut().unwrap().right = Some(2 * idx + 2);
+                if vert {
+                    split_range = (
+                        node.unwrap().coords.unwrap().0,
+                        node.unwrap().coords.unwrap().2,
+                    )
+                } else {
+                    split_range = (
+                        node.unwrap().coords.unwrap().1,
+                        node.unwrap().coords.unwrap().3,
+                    )
+                }
+            }
+            None => return Err(TreeError::IndexError),
+        }
+
+        //Check later for balance
+        split_pos = (split_range.0 + split_range.1) / 2;
+        //Offset from the start of the range so sub-dungeons that don't begin at 0 still split inside themselves
+        split_pos = split_range.0 + ((split_pos - split_range.0) as f32 * self.rng.gen_range(0.35..0.75)) as i32;
+
+        //Children live at 2n+1 and 2n+2, which can be past the end if earlier nodes were never split
+        self.nodes.resize(usize::max(self.nodes.len() + 3, 2 * root_idx + 3), None);
+        if vert {
+            self.nodes[2 * root_idx + 1] = Some(DungeonNode {
+                coords: Some((
+                    root_node.coords.unwrap().0 + 1,
+                    root_node.coords.unwrap().1 + 1,
+                    split_pos,
+                    root_node.coords.unwrap().3 - 1,
+                )),
+                left: None,
+                right: None,
+                room: None,
+                node_id: 2 * root_idx + 1,
+            });
+            self.nodes[2 * root_idx + 2] = Some(DungeonNode {
+                coords: Some((
+                    split_pos + 1,
+                    root_node.coords.unwrap().1 + 1,
+                    root_node.coords.unwrap().2 - 1,
+                    root_node.coords.unwrap().3 - 1,
+                )),
+                left: None,
+                right: None,
+                room: None,
+                node_id: 2 * root_idx + 2,
+            });
+        } else {
+            self.nodes[2 * root_idx + 1] = Some(DungeonNode {
+                coords: Some((
+                    root_node.coords.unwrap().0 + 1,
+                    root_node.coords.unwrap().1 + 1,
+                    root_node.coords.unwrap().2 - 1,
+                    split_pos,
+                )),
+                left: None,
+                right: None,
+                room: None,
+                node_id: 2 * root_idx + 1,
+            });
+            self.nodes[2 * root_idx + 2] = Some(DungeonNode {
+                coords: Some((
+                    root_node.coords.unwrap().0 + 1,
+                    split_pos + 1,
+                    root_node.coords.unwrap().2 - 1,
+                    root_node.coords.unwrap().3 - 1,
+                )),
+                left: None,
+                right: None,
+                room: None,
+                node_id: 2 * root_idx + 2,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn draw_to_file(&mut self) {
+        let width =
+            self.nodes[0].unwrap().coords.unwrap().2 - self.nodes[0].unwrap().coords.unwrap().0;
+        let height =
+            self.nodes[0].unwrap().coords.unwrap().3 - self.nodes[0].unwrap().coords.unwrap().1;
+
+        let path = Path::new("dung.out");
+        let display = path.display();
+        let mut grid: Vec<Vec<char>> = vec![vec![' '; height as usize]; width as usize];
+        let mut buf = String::new();
+        let mut rooms: Vec<Option<(i32, i32, i32, i32)>> = Vec::new();
+
+        let mut file = match File::create(&path) {
+            Err(why) => panic!("couldn't create {}: {}", display, why),
+            Ok(file) => file,
+        };
+
+        let itr = self.nodes.iter()
+        .filter(|node| node.is_some())
+        .filter(|node| node.unwrap().room.is_some());
+
+        for sub_dungeon in itr.enumerate() {
+            let x1 = sub_dungeon.1.unwrap().room.unwrap().0;
+            let y1 = sub_dungeon.1.unwrap().room.unwrap().1;
+            let x2 = sub_dungeon.1.unwrap().room.unwrap().2;
+            let y2 = sub_dungeon.1.unwrap().room.unwrap().3;
+
+            for y in y1..y2 {
+                for x in x1..x2 {
+                    if (y == y1 || y == y2 - 1) || (x == x1 || x == x2 - 1) {
+                        grid[y as usize][x as usize] = '*';
+                    } else {
+                        grid[y as usize][x as usize] = ' ';
+                    }
+                }
+            }
+
+            rooms.push(sub_dungeon.1.unwrap().room);
+            // let midX = (sub_dungeon.coords.unwrap().0 + sub_dungeon.coords.unwrap().2) / 2;
+            // let midY = (sub_dungeon.coords.unwrap().1 + sub_dungeon.coords.unwrap().3) / 2;
+            //Print node name
+            // let _ = stdout
+            //                 .queue(cursor::MoveTo(midX.try_into().unwrap(),midY.try_into().unwrap())).unwrap()
+            //                 .queue(style::Print(sub_dung_lbl));
+        }
+
+        
+       
+
+        for room in rooms {
+            if room == None {
+                continue;
+            }
+
+            let room_x1 = room.unwrap().0;
+            let room_y1 = room.unwrap().1;
+            let room_x2 = room.unwrap().2;
+            let room_y2 = room.unwrap().3;
+
+            for y in room_y1..room_y2 {
+                for x in room_x1..room_x2 {
+                    grid[y as usize][x as usize] = '.';
+                }
+            }
+        }
+
+        for line in grid {
+            for c in line {
+                buf.push_str(String::from(c).as_str());
+            }
+            buf.push('\n');
+        }
+
+        file.write(buf.as_str().as_bytes());
+
+        //Spawns go next to the map, one "entity_id x y" per line. The file is written even with nothing in it
+        //so spawns from an earlier run aren't left next to this map.
+        let spawn_path = Path::new("dung.spawns");
+        let mut spawn_file = match File::create(spawn_path) {
+            Err(why) => panic!("couldn't create {}: {}", spawn_path.display(), why),
+            Ok(file) => file,
+        };
+
+        let mut spawn_buf = String::new();
+        for spawn in self.spawns.iter() {
+            spawn_buf.push_str(&format!("{}\n", spawn));
+        }
+
+        let _ = spawn_file.write_all(spawn_buf.as_bytes());
+    }
+
+    pub fn draw_sub_dungeons(&self) {
+        let mut stdout = io::stdout();
+
+        let colors = [
+            "█".magenta(),
+            "█".red(),
+            "█".blue(),
+            "█".white(),
+            "█".green(),
+            "█".yellow(),
+        ];
+
+        //Skip drawing the base
+        let mut cpy = self.nodes.clone();
+        //cpy.remove(0);
+
+        stdout
+            .execute(terminal::Clear(terminal::ClearType::All))
+            .unwrap();
+
+        let mut sub_dung_lbl = 0;
+        for sub_dungeon in cpy {
+            let colr = sub_dung_lbl % colors.len();
+
+            for y in sub_dungeon.unwrap().coords.unwrap().1..=sub_dungeon.unwrap().coords.unwrap().3
+            {
+                for x in
+                    sub_dungeon.unwrap().coords.unwrap().0..=sub_dungeon.unwrap().coords.unwrap().2
+                {
+                    if (y == sub_dungeon.unwrap().coords.unwrap().1
+                        || y == sub_dungeon.unwrap().coords.unwrap().3)
+                        || (x == sub_dungeon.unwrap().coords.unwrap().0
+                            || x == sub_dungeon.unwrap().coords.unwrap().2)
+                    {
+                        let _ = stdout
+                            .queue(cursor::MoveTo(x.try_into().unwrap(), y.try_into().unwrap()))
+                            .unwrap()
+                            .queue(style::PrintStyledContent(colors[colr]));
+                    }
+                }
+            }
+
+            let midX = (sub_dungeon.unwrap().coords.unwrap().0
+                + sub_dungeon.unwrap().coords.unwrap().2)
+                / 2;
+            let midY = (sub_dungeon.unwrap().coords.unwrap().1
+                + sub_dungeon.unwrap().coords.unwrap().3)
+                / 2;
+            //Print node name
+            // let _ = stdout
+            //                 .queue(cursor::MoveTo(midX.try_into().unwrap(),midY.try_into().unwrap())).unwrap()
+            //                 .queue(style::Print(sub_dung_lbl));
+            sub_dung_lbl += 1;
+        }
+        stdout.flush().unwrap();
+    }
+
+    pub fn draw_rooms(&self) {
+        let mut stdout = io::stdout();
+
+        let colors = [
+            "█".magenta(),
+            "█".red(),
+            "█".blue(),
+            "█".white(),
+            "█".green(),
+            "█".yellow(),
+        ];
+
+        //Skip drawing the base
+        let mut cpy = self.nodes.clone();
+        
+        cpy.retain(|c| *c != None);
+
+        stdout.execute(terminal::Clear(terminal::ClearType::All)).unwrap();
+
+        let mut sub_dung_lbl = 0;
+        for sub_dungeon in cpy {
+            let colr = sub_dung_lbl % colors.len();
+
+            if (sub_dungeon.unwrap().room == None) {
+                continue;
+            }
+
+            for y in sub_dungeon.unwrap().room.unwrap().1..=sub_dungeon.unwrap().room.unwrap().3 {
+                for x in sub_dungeon.unwrap().room.unwrap().0..=sub_dungeon.unwrap().room.unwrap().2
+                {
+                    let _ = stdout
+                        .queue(cursor::MoveTo(x.try_into().unwrap(), y.try_into().unwrap()))
+                        .unwrap()
+                        .queue(style::PrintStyledContent(colors[colr]));
+                }
+            }
+
+            let midX =
+                (sub_dungeon.unwrap().room.unwrap().0 + sub_dungeon.unwrap().room.unwrap().2) / 2;
+            let midY =
+                (sub_dungeon.unwrap().room.unwrap().1 + sub_dungeon.unwrap().room.unwrap().3) / 2;
+            //Print node name
+            let _ = stdout
+                .queue(cursor::MoveTo(
+                    midX.try_into().unwrap(),
+                    midY.try_into().unwrap(),
+                ))
+                .unwrap()
+                .queue(style::Print(sub_dungeon.unwrap().node_id));
+            sub_dung_lbl += 1;
+        }
+        stdout.flush().unwrap();
+    }
+
+    pub fn print_tree_console(&self) {
+        print_tree(self);
+    }
+
+    pub fn draw_paths(&self)
+    {
+        let mut stdout = io::stdout();
+
+        for path in self.paths.clone()
+        {
+            for point in path.cells()
+            {
+                let _ = stdout
+                        .queue(cursor::MoveTo(point.0 as u16, point.1 as u16))
+                        .unwrap()
+                        .queue(style::PrintStyledContent("█".white()));
+            }
+        }
+
+        stdout.flush().unwrap();
+    }
+
+    //Heatmap of every walkable tile's danger, green near the entrance through to red furthest from it
+    pub fn draw_danger(&self) -> Result<(), TreeError>
+    {
+        self.danger_map()?.draw();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DungeonNode, DungeonTree};
+
+    #[test]
+    fn split_nodes_connect_through_their_closest_rooms() {
+        let distance = |a: &DungeonNode, b: &DungeonNode| {
+            let (a, b) = (DungeonTree::room_center(a.room.unwrap()), DungeonTree::room_center(b.room.unwrap()));
+            (a.0 - b.0).abs() + (a.1 - b.1).abs()
+        };
+
+        for _ in 0..20 {
+            let mut tree = DungeonTree::new(7);
+            tree.setRoot(DungeonNode { coords: Some((0, 0, 80, 60)), ..DungeonNode::new() }).unwrap();
+            tree.split_sub_dungeon(true, 0).unwrap();
+            tree.split_sub_dungeon(false, 1).unwrap();
+            tree.split_sub_dungeon(false, 2).unwrap();
+            tree.build_rooms((2, 2, 2, 2)).unwrap();
+            tree.gen_paths().unwrap();
+
+            //Not just the parents of leaves: the root joins its two halves too
+            let parents: Vec<DungeonNode> = tree.nodes.iter().flatten().copied().filter(|node| node.left.is_some() && node.right.is_some()).collect();
+            assert_eq!(parents.len(), 3);
+
+            for parent in parents {
+                let left = tree.get_subtree_rooms(parent.left.unwrap());
+                let right = tree.get_subtree_rooms(parent.right.unwrap());
+                if left.is_empty() || right.is_empty() {
+                    continue;
+                }
+
+                let closest = left.iter().flat_map(|a| right.iter().map(move |b| distance(a, b))).min().unwrap();
+                let joined = tree.paths.iter().filter_map(|path| path.rooms).any(|(a, b)| {
+                    left.iter().any(|node| node.node_id == a)
+                        && right.iter().any(|node| node.node_id == b)
+                        && distance(&tree.nodes[a].unwrap(), &tree.nodes[b].unwrap()) == closest
+                });
+                assert!(joined, "node {} isn't joined through its closest rooms", parent.node_id);
+            }
+        }
+    }
+}
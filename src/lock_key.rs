@@ -0,0 +1,288 @@
+use rand::Rng;
+use std::collections::HashSet;
+
+use crate::map::{brush_cells, DungeonMap, Tile};
+use crate::{DungeonTree, TreeError};
+
+//(path index, middle cell, tiles shut) of somewhere a door could go
+type DoorSpot = (usize, (i32, i32), Vec<(i32, i32)>);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LockedDoor {
+    pub lock_id: usize,
+    //Index into DungeonTree::paths of the corridor this door sits on
+    pub path_idx: usize,
+    pub position: (i32, i32),
+    //Every tile the door shuts, right across the corridor however wide it is
+    pub cells: Vec<(i32, i32)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Key {
+    pub lock_id: usize,
+    //node_id of the room holding the key
+    pub room: usize,
+    pub position: (i32, i32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Progression {
+    //node_id of the rooms the walk starts and ends in
+    pub start: usize,
+    pub exit: usize,
+    //Tiles the walk starts and ends on
+    pub start_position: (i32, i32),
+    pub exit_position: (i32, i32),
+    pub doors: Vec<LockedDoor>,
+    pub keys: Vec<Key>,
+}
+
+impl DungeonTree {
+    //Places num_locks locked doors on the corridors between the entrance and exit, then places each key in a room
+    //reachable without passing through its own door (or any later one). Everything is worked out on the rasterized
+    //map, so corridors crossing or running through rooms are walked like any other. Each door shuts its corridor's
+    //whole width and is only put somewhere the exit can't be reached around it.
+    //Without a placed entrance and exit, the first room and the room furthest from it are used.
+    //gen_paths must have been run first.
+    pub fn place_locks(&mut self, num_locks: usize) -> Result<Progression, TreeError> {
+        let map = self.rasterize();
+
+        let mut centers: Vec<(usize, (i32, i32))> = map
+            .regions()
+            .iter()
+            .filter_map(|region| Some((region.id, region.center()?)))
+            .collect();
+        centers.sort();
+
+        if centers.is_empty() {
+            return Err(TreeError::NoLeavesError);
+        }
+
+        let (start, start_position) = match &self.entrance_exit {
+            Some(placed) => (placed.entrance_room, placed.entrance),
+            None => centers[0],
+        };
+        let distances = map.distances_from(start_position);
+        let distance = |point: (i32, i32)| map.index(point).and_then(|idx| distances[idx]);
+
+        //The exit is the room furthest from the start to walk to
+        let (exit, exit_position) = match &self.entrance_exit {
+            Some(placed) => (placed.exit_room, placed.exit),
+            None => *centers
+                .iter()
+                .filter(|(_, center)| distance(*center).is_some())
+                .max_by_key(|(_, center)| distance(*center))
+                .unwrap(),
+        };
+        if distance(exit_position).is_none() {
+            return Err(TreeError::DisconnectedError);
+        }
+
+        //Every way to the exit goes along the shortest one somewhere, so only tiles on it are tried for doors
+        let mut route: HashSet<(i32, i32)> = HashSet::from([exit_position]);
+        let mut curr = exit_position;
+        while curr != start_position {
+            let dist = distance(curr).unwrap();
+            curr = map.neighbours(curr).into_iter().find(|next| distance(*next) == Some(dist - 1)).unwrap();
+            route.insert(curr);
+        }
+
+        //A door has to shut nothing but corridor, so it's never in a room, and be the only way through, which is when
+        //shutting it cuts the start off from the exit. It also has to leave a room on the start side for the first key,
+        //which a door on the spur out to an entrance on the edge wouldn't.
+        //Spots shutting off the same amount of the dungeon are on the same stretch of corridor, so they're grouped
+        //by how much can still be reached, nearest the start first.
+        let mut tried: HashSet<(i32, i32)> = HashSet::new();
+        let mut stretches: Vec<(usize, Vec<DoorSpot>)> = Vec::new();
+
+        for (path_idx, path) in self.paths.iter().enumerate() {
+            for cell in path.cells() {
+                if !route.contains(&cell) || !tried.insert(cell) {
+                    continue;
+                }
+
+                let cells: Vec<(i32, i32)> = brush_cells(cell, path.width).filter(|dug| map.is_walkable(*dug)).collect();
+                if cells.iter().any(|dug| map.get(*dug) != Some(Tile::Corridor) || *dug == start_position || *dug == exit_position) {
+                    continue;
+                }
+
+                let reachable = DungeonTree::reachable(&map, start_position, &cells);
+                let room_open = centers.iter().any(|(_, center)| reachable[map.index(*center).unwrap()]);
+                if reachable[map.index(exit_position).unwrap()] || !room_open {
+                    continue;
+                }
+
+                let reached = reachable.iter().filter(|open| **open).count();
+                match stretches.iter_mut().find(|(count, _)| *count == reached) {
+                    Some((_, spots)) => spots.push((path_idx, cell, cells)),
+                    None => stretches.push((reached, vec![(path_idx, cell, cells)])),
+                }
+            }
+        }
+        stretches.sort_by_key(|(reached, _)| *reached);
+
+        if num_locks > stretches.len() {
+            return Err(TreeError::LockPlacementError);
+        }
+
+        //Spread the doors out evenly along the way, each in the middle of its stretch
+        let mut doors: Vec<LockedDoor> = Vec::new();
+        let count = stretches.len();
+        for lock_id in 0..num_locks {
+            let (_, spots) = &mut stretches[(lock_id + 1) * count / (num_locks + 1)];
+            spots.sort_by_key(|(_, cell, _)| distance(*cell));
+            let (path_idx, position, cells) = spots[spots.len() / 2].clone();
+
+            doors.push(LockedDoor {
+                lock_id,
+                path_idx,
+                position,
+                cells,
+            });
+        }
+
+        let mut keys: Vec<Key> = Vec::new();
+        let mut previously_reachable: HashSet<usize> = HashSet::new();
+        for lock_id in 0..num_locks {
+            //With the keys for every earlier door in hand, only this door and the ones after it block the way
+            let shut: Vec<(i32, i32)> = doors[lock_id..].iter().flat_map(|door| door.cells.iter().copied()).collect();
+            let open = DungeonTree::reachable(&map, start_position, &shut);
+            let reachable: HashSet<usize> = centers
+                .iter()
+                .filter(|(_, center)| open[map.index(*center).unwrap()])
+                .map(|(room, _)| *room)
+                .collect();
+
+            //Prefer rooms just opened up by the previous door so the player has to go through it
+            let mut candidates: Vec<usize> = reachable.difference(&previously_reachable).copied().collect();
+            if candidates.is_empty() {
+                candidates = reachable.iter().copied().collect();
+            }
+            candidates.sort();
+
+            let room = candidates[self.rng.gen_range(0..candidates.len())];
+            keys.push(Key {
+                lock_id,
+                room,
+                position: centers.iter().find(|(id, _)| *id == room).unwrap().1,
+            });
+
+            previously_reachable = reachable;
+        }
+
+        let progression = Progression {
+            start,
+            exit,
+            start_position,
+            exit_position,
+            doors,
+            keys,
+        };
+
+        self.solve_locks(&progression)?;
+        self.progression = Some(progression.clone());
+        self.update_room_graph();
+
+        Ok(progression)
+    }
+
+    //Walks the rasterized map from the start picking up every key it can reach and opening doors it holds keys for.
+    //Ok if the exit is eventually reached.
+    pub fn solve_locks(&self, progression: &Progression) -> Result<(), TreeError> {
+        let map = self.rasterize();
+        let mut held: HashSet<usize> = HashSet::new();
+
+        loop {
+            let shut: Vec<(i32, i32)> = progression
+                .doors
+                .iter()
+                .filter(|door| !held.contains(&door.lock_id))
+                .flat_map(|door| door.cells.iter().copied())
+                .collect();
+            let open = DungeonTree::reachable(&map, progression.start_position, &shut);
+            let reached = |point: (i32, i32)| map.index(point).is_some_and(|idx| open[idx]);
+
+            if reached(progression.exit_position) {
+                return Ok(());
+            }
+
+            let found: Vec<usize> = progression
+                .keys
+                .iter()
+                .filter(|key| reached(key.position) && !held.contains(&key.lock_id))
+                .map(|key| key.lock_id)
+                .collect();
+
+            if found.is_empty() {
+                return Err(TreeError::UnsolvableError);
+            }
+
+            held.extend(found);
+        }
+    }
+
+    //Which tiles can be walked to from start with the shut tiles walled up, indexed like the map's tiles
+    fn reachable(map: &DungeonMap, start: (i32, i32), shut: &[(i32, i32)]) -> Vec<bool> {
+        let mut walled = map.clone();
+        for cell in shut {
+            walled.set(*cell, Tile::Wall);
+        }
+
+        walled.distances_from(start).iter().map(|dist| dist.is_some()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::map::Tile;
+    use crate::{split_dirs, Dungeon, TreeError};
+
+    //Walls up every door and checks the exit can't be reached from the start some other way
+    fn assert_doors_cut(dungeon: &Dungeon, progression: &super::Progression) {
+        let mut map = dungeon.tree.rasterize();
+        for door in progression.doors.iter() {
+            for cell in door.cells.iter() {
+                map.set(*cell, Tile::Wall);
+            }
+        }
+
+        let distances = map.distances_from(progression.start_position);
+        assert!(distances[map.index(progression.exit_position).unwrap()].is_none(), "exit reachable around the locked doors");
+    }
+
+    #[test]
+    fn locked_doors_cannot_be_walked_around() {
+        let mut placed = 0;
+        let cases = [(1, 0.0, false), (3, 0.0, true), (1, 0.5, true), (3, 0.5, false)].into_iter().cycle();
+        for (seed, (width, loops, on_edge)) in (0..80).zip(cases) {
+            let mut dungeon = Dungeon::new((0, 0, 80, 60), 4, split_dirs::RANDOM);
+            dungeon.tree.set_seed(seed);
+            dungeon.split_all((2, 2, 2, 2)).unwrap();
+            dungeon.tree.build_rooms((2, 2, 2, 2)).unwrap();
+            dungeon.tree.set_corridor_width(width);
+            dungeon.tree.gen_paths().unwrap();
+            if loops > 0.0 {
+                let _ = dungeon.tree.add_loops(loops);
+            }
+            if on_edge {
+                dungeon.tree.place_entrance_exit(true).unwrap();
+            }
+
+            let progression = match dungeon.tree.place_locks(2) {
+                Ok(progression) => progression,
+                Err(TreeError::LockPlacementError) => continue,
+                Err(err) => panic!("{}", err),
+            };
+            placed += 1;
+
+            assert_eq!(progression.doors.len(), 2);
+            for door in progression.doors.iter() {
+                assert!(door.cells.len() >= width as usize, "door narrower than its corridor");
+            }
+            assert_doors_cut(&dungeon, &progression);
+            dungeon.tree.solve_locks(&progression).unwrap();
+        }
+
+        assert!(placed > 40, "locks placed in only {} of 80 dungeons", placed);
+    }
+}
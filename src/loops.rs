@@ -0,0 +1,131 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{DungeonNode, DungeonTree, TreeError};
+
+//A loop is only worth adding if walking between the two rooms is at least this many times longer than the straight line
+const MIN_SHORTCUT_GAIN: f32 = 1.5;
+
+impl DungeonTree {
+    //Rooms that face each other along x or y with no other room in the space between them
+    fn is_adjacent(a: (i32, i32, i32, i32), b: (i32, i32, i32, i32), rooms: &[DungeonNode]) -> bool {
+        let overlap_x = (i32::max(a.0, b.0), i32::min(a.2, b.2));
+        let overlap_y = (i32::max(a.1, b.1), i32::min(a.3, b.3));
+
+        //Space between the two facing walls
+        let between = if overlap_y.0 < overlap_y.1 && overlap_x.0 >= overlap_x.1 {
+            (overlap_x.1, overlap_y.0, overlap_x.0, overlap_y.1)
+        } else if overlap_x.0 < overlap_x.1 && overlap_y.0 >= overlap_y.1 {
+            (overlap_x.0, overlap_y.1, overlap_x.1, overlap_y.0)
+        } else {
+            return false;
+        };
+
+        !rooms.iter().filter_map(|node| node.room).any(|room| {
+            room != a && room != b && room.0 < between.2 && between.0 < room.2 && room.1 < between.3 && between.1 < room.3
+        })
+    }
+
+    //Adds up to loop_ratio * (number of paths) extra paths between adjacent rooms that aren't joined yet,
+    //picking the pairs whose walk is longest compared to the straight line between them first.
+    //Returns how many were added. gen_paths must have been run first.
+    pub fn add_loops(&mut self, loop_ratio: f32) -> Result<usize, TreeError> {
+        let rooms: Vec<DungeonNode> = self.get_subtree_rooms(0);
+        let wanted = (self.paths.len() as f32 * loop_ratio.max(0.0)).round() as usize;
+
+        let joined: HashSet<(usize, usize)> = self
+            .paths
+            .iter()
+            .filter_map(|path| path.rooms)
+            .map(|(a, b)| (usize::min(a, b), usize::max(a, b)))
+            .collect();
+
+        let mut candidates: Vec<(usize, usize)> = Vec::new();
+        for (i, a) in rooms.iter().enumerate() {
+            for b in rooms[i + 1..].iter() {
+                let pair = (usize::min(a.node_id, b.node_id), usize::max(a.node_id, b.node_id));
+                if !joined.contains(&pair) && DungeonTree::is_adjacent(a.room.unwrap(), b.room.unwrap(), &rooms) {
+                    candidates.push(pair);
+                }
+            }
+        }
+
+        let mut added = 0;
+
+        while added < wanted && !candidates.is_empty() {
+            //Walking distances change with every loop added, so score against the current map,
+            //walking out from each room once however many candidates it's in
+            let map = self.rasterize();
+            let mut distances: HashMap<usize, Vec<Option<usize>>> = HashMap::new();
+            let mut best: Option<(usize, f32)> = None;
+
+            for (idx, (a, b)) in candidates.iter().enumerate() {
+                let center_a = DungeonTree::room_center(self.nodes[*a].unwrap().room.unwrap());
+                let center_b = DungeonTree::room_center(self.nodes[*b].unwrap().room.unwrap());
+                let straight = ((center_a.0 - center_b.0).abs() + (center_a.1 - center_b.1).abs()).max(1) as f32;
+                let from_a = distances.entry(*a).or_insert_with(|| map.distances_from(center_a));
+
+                let gain = match map.index(center_b).and_then(|goal| from_a[goal]) {
+                    Some(walk) => walk as f32 / straight,
+                    None => f32::INFINITY,
+                };
+
+                if best.is_none() || gain > best.unwrap().1 {
+                    best = Some((idx, gain));
+                }
+            }
+
+            let (idx, gain) = best.unwrap();
+            if gain < MIN_SHORTCUT_GAIN {
+                break;
+            }
+
+            //A loop with no way through the mask is just left out
+            let pair = candidates.swap_remove(idx);
+            if self.connect_rooms(&[pair]).is_ok() {
+                added += 1;
+            }
+        }
+
+        Ok(added)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MIN_SHORTCUT_GAIN;
+    use crate::{split_dirs, Dungeon, DungeonTree};
+
+    #[test]
+    fn every_loop_is_a_worthwhile_shortcut() {
+        let mut added = 0;
+
+        for seed in 0..30 {
+            let mut dungeon = Dungeon::new((0, 0, 80, 60), 5, split_dirs::RANDOM);
+            dungeon.tree.set_seed(seed);
+            dungeon.generate((2, 2, 2, 2)).unwrap();
+            let before = dungeon.tree.paths.len();
+            added += dungeon.tree.add_loops(0.5).unwrap();
+
+            for idx in before..dungeon.tree.paths.len() {
+                let (a, b) = dungeon.tree.paths[idx].rooms.unwrap();
+                let center_a = DungeonTree::room_center(dungeon.tree.nodes[a].unwrap().room.unwrap());
+                let center_b = DungeonTree::room_center(dungeon.tree.nodes[b].unwrap().room.unwrap());
+
+                //Walking between the rooms with the paths up to the loop, then with it
+                let walk = |paths: usize| {
+                    let mut tree = dungeon.tree.clone();
+                    tree.paths.truncate(paths);
+                    let map = tree.rasterize();
+                    map.distances_from(center_a)[map.index(center_b).unwrap()]
+                };
+                let (without, with) = (walk(idx), walk(idx + 1).unwrap());
+
+                if let Some(without) = without {
+                    assert!(without as f32 >= with as f32 * MIN_SHORTCUT_GAIN, "{} to {} only went from {} to {}", a, b, without, with);
+                }
+            }
+        }
+
+        assert!(added > 0);
+    }
+}
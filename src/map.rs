@@ -0,0 +1,445 @@
+use crossterm::{
+    cursor,
+    style::{self, Stylize},
+    terminal, ExecutableCommand, QueueableCommand,
+};
+use std::collections::{HashSet, VecDeque};
+use std::io::{self, Write};
+
+use crate::DungeonTree;
+
+//North, south, east and west
+pub const DIRECTIONS: [(i32, i32); 4] = [(0, -1), (0, 1), (1, 0), (-1, 0)];
+
+//Whether point is inside rect (x1, y1, x2, y2), with x2 and y2 excluded like every rect in the tree
+pub fn rect_contains(rect: (i32, i32, i32, i32), point: (i32, i32)) -> bool {
+    point.0 >= rect.0 && point.0 < rect.2 && point.1 >= rect.1 && point.1 < rect.3
+}
+
+//Cells of the width by width square centered on point that a corridor of that width digs.
+//Even widths reach a tile further right and down than left and up.
+pub fn brush_cells(point: (i32, i32), width: i32) -> impl Iterator<Item = (i32, i32)> {
+    let (low, high) = (-(width - 1) / 2, width / 2);
+    (low..=high).flat_map(move |dy| (low..=high).map(move |dx| (point.0 + dx, point.1 + dy)))
+}
+
+//Position of point in anything laid out row by row over rect, None if it's outside
+pub fn rect_index(rect: (i32, i32, i32, i32), point: (i32, i32)) -> Option<usize> {
+    match rect_contains(rect, point) {
+        true => Some(((point.1 - rect.1) * (rect.2 - rect.0) + (point.0 - rect.0)) as usize),
+        false => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Tile {
+    Empty,
+    Wall,
+    Floor,
+    Corridor,
+    Door,
+}
+
+impl Tile {
+    pub fn is_walkable(&self) -> bool {
+        matches!(self, Tile::Floor | Tile::Corridor | Tile::Door)
+    }
+
+    pub fn to_char(&self) -> char {
+        match self {
+            Tile::Empty => ' ',
+            Tile::Wall => '*',
+            Tile::Floor => '.',
+            Tile::Corridor => '#',
+            Tile::Door => '+',
+        }
+    }
+
+    pub fn from_char(c: char) -> Option<Tile> {
+        match c {
+            ' ' => Some(Tile::Empty),
+            '*' => Some(Tile::Wall),
+            '.' => Some(Tile::Floor),
+            '#' => Some(Tile::Corridor),
+            '+' => Some(Tile::Door),
+            _ => None,
+        }
+    }
+}
+
+//A named part of a map, like one of the tree's rooms or one open pocket of a cave
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    //node_id for rooms from the tree, otherwise numbered by whatever made the map
+    pub id: usize,
+    pub bounds: (i32, i32, i32, i32),
+    //Walkable tiles belonging to the region
+    pub cells: Vec<(i32, i32)>,
+}
+
+impl Region {
+    //The cell closest to the middle of the bounds, which for a room from the tree is its center
+    pub fn center(&self) -> Option<(i32, i32)> {
+        let mid = ((self.bounds.0 + self.bounds.2) / 2, (self.bounds.1 + self.bounds.3) / 2);
+
+        self.cells
+            .iter()
+            .min_by_key(|cell| (cell.0 - mid.0).abs() + (cell.1 - mid.1).abs())
+            .copied()
+    }
+}
+
+//A grid of tiles covering bounds (x1, y1, x2, y2), with x2 and y2 excluded like the rooms drawn by draw_to_file.
+//Everything is indexed by the same coordinates the tree uses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DungeonMap {
+    pub bounds: (i32, i32, i32, i32),
+    tiles: Vec<Tile>,
+    regions: Vec<Region>,
+}
+
+impl DungeonMap {
+    pub fn new(bounds: (i32, i32, i32, i32)) -> DungeonMap {
+        let width = i32::max(bounds.2 - bounds.0, 0) as usize;
+        let height = i32::max(bounds.3 - bounds.1, 0) as usize;
+
+        DungeonMap {
+            bounds,
+            tiles: vec![Tile::Empty; width * height],
+            regions: Vec::new(),
+        }
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    pub fn region(&self, id: usize) -> Option<&Region> {
+        self.regions.iter().find(|region| region.id == id)
+    }
+
+    pub fn add_region(&mut self, region: Region) {
+        self.regions.push(region);
+    }
+
+    //Region a walkable tile belongs to
+    pub fn region_at(&self, point: (i32, i32)) -> Option<&Region> {
+        self.regions.iter().find(|region| region.cells.contains(&point))
+    }
+
+    pub fn width(&self) -> i32 {
+        self.bounds.2 - self.bounds.0
+    }
+
+    pub fn height(&self) -> i32 {
+        self.bounds.3 - self.bounds.1
+    }
+
+    pub fn in_bounds(&self, point: (i32, i32)) -> bool {
+        rect_contains(self.bounds, point)
+    }
+
+    //Position of a point in the tile vector, or in any other vector laid out the same way
+    pub fn index(&self, point: (i32, i32)) -> Option<usize> {
+        rect_index(self.bounds, point)
+    }
+
+    pub fn point(&self, idx: usize) -> (i32, i32) {
+        let idx = idx as i32;
+        (self.bounds.0 + idx % self.width(), self.bounds.1 + idx / self.width())
+    }
+
+    pub fn get(&self, point: (i32, i32)) -> Option<Tile> {
+        self.index(point).map(|idx| self.tiles[idx])
+    }
+
+    //Points out of bounds are ignored
+    pub fn set(&mut self, point: (i32, i32), tile: Tile) {
+        if let Some(idx) = self.index(point) {
+            self.tiles[idx] = tile;
+        }
+    }
+
+    //Digs a path through a point: a wall becomes a door, empty space becomes corridor, anything else is left be
+    pub fn carve(&mut self, point: (i32, i32)) {
+        match self.get(point) {
+            Some(Tile::Wall) => self.set(point, Tile::Door),
+            Some(Tile::Empty) => self.set(point, Tile::Corridor),
+            _ => {}
+        }
+    }
+
+    //Digs a width by width square centered on point. Only the center is dug through room corners,
+    //so a wide corridor meeting a room opens up its wall without breaking the corners.
+    pub fn carve_brush(&mut self, point: (i32, i32), width: i32) {
+        for cell in brush_cells(point, width) {
+            if cell == point || !self.is_corner(cell) {
+                self.carve(cell);
+            }
+        }
+    }
+
+    //A wall with more wall both beside it and above or below it
+    fn is_corner(&self, point: (i32, i32)) -> bool {
+        let is_wall = |dx: i32, dy: i32| matches!(self.get((point.0 + dx, point.1 + dy)), Some(Tile::Wall) | Some(Tile::Door));
+
+        self.get(point) == Some(Tile::Wall) && (is_wall(-1, 0) || is_wall(1, 0)) && (is_wall(0, -1) || is_wall(0, 1))
+    }
+
+    pub fn is_walkable(&self, point: (i32, i32)) -> bool {
+        self.get(point).is_some_and(|tile| tile.is_walkable())
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    //In bounds north, south, east and west neighbours of a point
+    pub fn neighbours(&self, point: (i32, i32)) -> Vec<(i32, i32)> {
+        DIRECTIONS
+            .iter()
+            .map(|(dx, dy)| (point.0 + dx, point.1 + dy))
+            .filter(|next| self.in_bounds(*next))
+            .collect()
+    }
+
+    //Groups of walkable tiles joined north, south, east or west, biggest first
+    pub fn pockets(&self) -> Vec<Vec<(i32, i32)>> {
+        let mut seen: Vec<bool> = vec![false; self.tiles.len()];
+        let mut pockets: Vec<Vec<(i32, i32)>> = Vec::new();
+
+        for idx in 0..self.tiles.len() {
+            if seen[idx] || !self.tiles[idx].is_walkable() {
+                continue;
+            }
+
+            let mut pocket: Vec<(i32, i32)> = Vec::new();
+            let mut queue: VecDeque<(i32, i32)> = VecDeque::from([self.point(idx)]);
+            seen[idx] = true;
+
+            while let Some(point) = queue.pop_front() {
+                pocket.push(point);
+
+                for next in self.neighbours(point) {
+                    let next_idx = self.index(next).unwrap();
+                    if !seen[next_idx] && self.tiles[next_idx].is_walkable() {
+                        seen[next_idx] = true;
+                        queue.push_back(next);
+                    }
+                }
+            }
+
+            pockets.push(pocket);
+        }
+
+        pockets.sort_by_key(|pocket| std::cmp::Reverse(pocket.len()));
+        pockets
+    }
+
+    //Smallest (x1, y1, x2, y2) holding every cell, with x2 and y2 excluded
+    pub fn bounding_box(cells: &[(i32, i32)]) -> (i32, i32, i32, i32) {
+        (
+            cells.iter().map(|cell| cell.0).min().unwrap_or(0),
+            cells.iter().map(|cell| cell.1).min().unwrap_or(0),
+            cells.iter().map(|cell| cell.0 + 1).max().unwrap_or(0),
+            cells.iter().map(|cell| cell.1 + 1).max().unwrap_or(0),
+        )
+    }
+
+    //Puts a wall on every empty tile touching a walkable one, diagonals included
+    pub fn wall_in(&mut self) {
+        for idx in 0..self.tiles.len() {
+            if self.tiles[idx] != Tile::Empty {
+                continue;
+            }
+
+            let point = self.point(idx);
+            let touching = (-1..=1).any(|dy| (-1..=1).any(|dx| self.is_walkable((point.0 + dx, point.1 + dy))));
+            if touching {
+                self.tiles[idx] = Tile::Wall;
+            }
+        }
+    }
+
+    //Walking distance from start to every tile, indexed like the tile vector. None where it can't be walked to.
+    pub fn distances_from(&self, start: (i32, i32)) -> Vec<Option<usize>> {
+        let mut distances: Vec<Option<usize>> = vec![None; self.tiles.len()];
+        let mut queue: VecDeque<(i32, i32)> = VecDeque::new();
+
+        if !self.is_walkable(start) {
+            return distances;
+        }
+
+        distances[self.index(start).unwrap()] = Some(0);
+        queue.push_back(start);
+
+        while let Some(point) = queue.pop_front() {
+            let dist = distances[self.index(point).unwrap()].unwrap();
+
+            for next in self.neighbours(point) {
+                let idx = self.index(next).unwrap();
+                if distances[idx].is_none() && self.tiles[idx].is_walkable() {
+                    distances[idx] = Some(dist + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        distances
+    }
+
+    //Same layout as dung.out, one character per tile
+    pub fn to_ascii(&self) -> String {
+        let mut buf = String::with_capacity(self.tiles.len() + self.height() as usize);
+
+        for row in self.tiles.chunks(self.width().max(1) as usize) {
+            for tile in row {
+                buf.push(tile.to_char());
+            }
+            buf.push('\n');
+        }
+
+        buf
+    }
+
+    //Draws every tile to the terminal, giving each region's floor its own colour
+    pub fn draw(&self) {
+        let mut stdout = io::stdout();
+
+        let colors = [
+            style::Color::Magenta,
+            style::Color::Red,
+            style::Color::Blue,
+            style::Color::Green,
+            style::Color::Yellow,
+            style::Color::Cyan,
+        ];
+
+        let mut region_of: Vec<Option<usize>> = vec![None; self.tiles.len()];
+        for (region_idx, region) in self.regions.iter().enumerate() {
+            for cell in region.cells.iter() {
+                if let Some(idx) = self.index(*cell) {
+                    region_of[idx] = Some(region_idx);
+                }
+            }
+        }
+
+        stdout.execute(terminal::Clear(terminal::ClearType::All)).unwrap();
+
+        for (idx, tile) in self.tiles.iter().enumerate() {
+            let colr = match (tile, region_of[idx]) {
+                (Tile::Empty, _) => continue,
+                (Tile::Wall, _) => style::Color::DarkGrey,
+                (Tile::Floor, Some(region_idx)) => colors[region_idx % colors.len()],
+                (Tile::Floor, None) | (Tile::Corridor, _) => style::Color::White,
+                (Tile::Door, _) => style::Color::DarkYellow,
+            };
+            let point = self.point(idx);
+
+            let _ = stdout
+                .queue(cursor::MoveTo(point.0 as u16, point.1 as u16))
+                .unwrap()
+                .queue(style::PrintStyledContent("█".with(colr)));
+        }
+
+        stdout.flush().unwrap();
+    }
+}
+
+impl DungeonTree {
+    //Turns the doors a corridor made going through a room it wasn't dug to reach back into floor,
+    //unless a corridor that was dug to reach that room also made them. Paths that don't join two rooms,
+    //like entrance spurs, keep their doors.
+    fn open_pass_throughs(&self, map: &mut DungeonMap) {
+        let width_of = |path_idx: usize| self.paths.get(path_idx).map_or(1, |path| path.width);
+
+        let kept: HashSet<(i32, i32)> = self
+            .room_graph
+            .edges()
+            .iter()
+            .flat_map(|edge| edge.doors.iter().flat_map(|door| brush_cells(*door, width_of(edge.path_idx))))
+            .collect();
+
+        let joins_rooms = |path_idx: &usize| self.paths.get(*path_idx).is_some_and(|path| path.rooms.is_some());
+
+        for junction in self.room_graph.junctions().iter().filter(|junction| junction.room.is_some() && junction.paths.iter().any(joins_rooms)) {
+            let width = width_of(junction.paths[0]);
+
+            for cell in junction.cells.iter().flat_map(|cell| brush_cells(*cell, width)) {
+                if map.get(cell) == Some(Tile::Door) && !kept.contains(&cell) {
+                    map.set(cell, Tile::Floor);
+                }
+            }
+        }
+    }
+
+    //Lays the rooms and paths out on a DungeonMap covering the root node.
+    //Room edges are walls, and a path crossing a wall leaves a door in it.
+    //Every room with floor in it becomes a region with its node_id.
+    pub fn rasterize(&self) -> DungeonMap {
+        let mut map = DungeonMap::new(self.nodes[0].unwrap().coords.unwrap());
+
+        for node in self.nodes.iter().filter_map(|node| *node) {
+            if node.left.is_some() || node.right.is_some() {
+                continue;
+            }
+
+            if let Some((x1, y1, x2, y2)) = node.room {
+                let interior = self.interiors.get(&node.node_id);
+                let mut cells: Vec<(i32, i32)> = Vec::new();
+
+                for y in y1..y2 {
+                    for x in x1..x2 {
+                        let tile = match interior {
+                            _ if y == y1 || y == y2 - 1 || x == x1 || x == x2 - 1 => Tile::Wall,
+                            Some(interior) => interior.get((x, y)).unwrap_or(Tile::Empty),
+                            None => Tile::Floor,
+                        };
+
+                        map.set((x, y), tile);
+                        if tile.is_walkable() {
+                            cells.push((x, y));
+                        }
+                    }
+                }
+
+                if !cells.is_empty() {
+                    map.add_region(Region {
+                        id: node.node_id,
+                        bounds: (x1, y1, x2, y2),
+                        cells,
+                    });
+                }
+            }
+        }
+
+        for path in self.paths.iter() {
+            for point in path.cells() {
+                map.carve_brush(point, path.width);
+            }
+        }
+
+        if !self.pass_through_doors {
+            self.open_pass_throughs(&mut map);
+        }
+
+        //Paths tunnel through the rock of a built interior rather than leaving doors in it
+        for interior in self.interiors.values() {
+            let (x1, y1, x2, y2) = interior.bounds;
+
+            for y in y1 + 1..y2 - 1 {
+                for x in x1 + 1..x2 - 1 {
+                    if map.get((x, y)) == Some(Tile::Door) {
+                        map.set((x, y), Tile::Floor);
+                    }
+                }
+            }
+        }
+
+        map
+    }
+}
@@ -0,0 +1,296 @@
+use std::fs;
+use std::path::Path;
+
+use crate::map::{rect_index, DungeonMap, Tile};
+use crate::{DungeonTree, TreeError};
+
+//Which tiles a dungeon is allowed to use. Anything outside the mask's bounds is outside the mask.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mask {
+    pub bounds: (i32, i32, i32, i32),
+    //Row by row over bounds
+    inside: Vec<bool>,
+}
+
+impl Mask {
+    pub fn from_fn(bounds: (i32, i32, i32, i32), inside: impl Fn((i32, i32)) -> bool) -> Mask {
+        let inside = (bounds.1..bounds.3)
+            .flat_map(|y| (bounds.0..bounds.2).map(move |x| (x, y)))
+            .map(inside)
+            .collect();
+
+        Mask { bounds, inside }
+    }
+
+    //Tiles no further than radius from center
+    pub fn circle(center: (i32, i32), radius: i32) -> Mask {
+        let bounds = (center.0 - radius, center.1 - radius, center.0 + radius + 1, center.1 + radius + 1);
+
+        Mask::from_fn(bounds, |(x, y)| (x - center.0).pow(2) + (y - center.1).pow(2) <= radius.pow(2))
+    }
+
+    //Tiles whose middle is inside the polygon, by the even-odd rule so it can cross itself
+    pub fn polygon(points: &[(i32, i32)]) -> Mask {
+        if points.is_empty() {
+            return Mask::from_fn((0, 0, 0, 0), |_| false);
+        }
+
+        let bounds = (
+            points.iter().map(|point| point.0).min().unwrap(),
+            points.iter().map(|point| point.1).min().unwrap(),
+            points.iter().map(|point| point.0).max().unwrap() + 1,
+            points.iter().map(|point| point.1).max().unwrap() + 1,
+        );
+
+        Mask::from_fn(bounds, |(x, y)| {
+            let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+            let mut inside = false;
+
+            for (i, a) in points.iter().enumerate() {
+                let b = points[(i + 1) % points.len()];
+                let (ax, ay, bx, by) = (a.0 as f64, a.1 as f64, b.0 as f64, b.1 as f64);
+
+                if (ay > py) != (by > py) && px < ax + (py - ay) * (bx - ax) / (by - ay) {
+                    inside = !inside;
+                }
+            }
+
+            inside
+        })
+    }
+
+    //Drawn with one character per tile from (0, 0), spaces outside and anything else inside
+    pub fn from_ascii(text: &str) -> Mask {
+        let rows: Vec<Vec<bool>> = text.lines().map(|line| line.chars().map(|c| c != ' ').collect()).collect();
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0) as i32;
+
+        Mask::from_fn((0, 0, width, rows.len() as i32), |(x, y)| rows[y as usize].get(x as usize).copied().unwrap_or(false))
+    }
+
+    //Plain (P1) or raw (P4) PBM image from (0, 0), with black pixels inside
+    pub fn from_pbm(data: &[u8]) -> Result<Mask, TreeError> {
+        let mut pos = 0;
+
+        //Header fields are separated by whitespace, with comments running from # to the end of the line
+        let next_token = |pos: &mut usize| -> Option<String> {
+            loop {
+                match data.get(*pos)? {
+                    b'#' => {
+                        while data.get(*pos).is_some_and(|byte| *byte != b'\n') {
+                            *pos += 1;
+                        }
+                    }
+                    byte if byte.is_ascii_whitespace() => *pos += 1,
+                    _ => break,
+                }
+            }
+
+            let start = *pos;
+            while data.get(*pos).is_some_and(|byte| !byte.is_ascii_whitespace()) {
+                *pos += 1;
+            }
+            Some(String::from_utf8_lossy(&data[start..*pos]).into_owned())
+        };
+
+        let magic = next_token(&mut pos).ok_or(TreeError::MaskFormatError)?;
+        let mut size = || next_token(&mut pos).and_then(|token| token.parse::<usize>().ok()).ok_or(TreeError::MaskFormatError);
+        let (width, height) = (size()?, size()?);
+
+        let pixels: Vec<bool> = match magic.as_str() {
+            "P1" => data[pos..]
+                .iter()
+                .filter(|byte| **byte == b'0' || **byte == b'1')
+                .map(|byte| *byte == b'1')
+                .take(width * height)
+                .collect(),
+            "P4" => {
+                //One whitespace byte after the height, then rows packed 8 pixels to a byte
+                let row_bytes = width.div_ceil(8);
+                let raster = data.get(pos + 1..).ok_or(TreeError::MaskFormatError)?;
+
+                (0..height)
+                    .flat_map(|y| (0..width).map(move |x| (x, y)))
+                    .filter_map(|(x, y)| raster.get(y * row_bytes + x / 8).map(|byte| byte & (0x80 >> (x % 8)) != 0))
+                    .collect()
+            }
+            _ => return Err(TreeError::MaskFormatError),
+        };
+
+        if pixels.len() != width * height {
+            return Err(TreeError::MaskFormatError);
+        }
+
+        Ok(Mask {
+            bounds: (0, 0, width as i32, height as i32),
+            inside: pixels,
+        })
+    }
+
+    //Reads a PBM image, or an ASCII drawing if the file doesn't start like one
+    pub fn load(path: &Path) -> Result<Mask, TreeError> {
+        let data = fs::read(path).map_err(|err| TreeError::MaskReadError(err.to_string()))?;
+
+        match data.starts_with(b"P1") || data.starts_with(b"P4") {
+            true => Mask::from_pbm(&data),
+            false => Ok(Mask::from_ascii(&String::from_utf8_lossy(&data))),
+        }
+    }
+
+    pub fn contains(&self, point: (i32, i32)) -> bool {
+        rect_index(self.bounds, point).is_some_and(|idx| self.inside[idx])
+    }
+
+    //Whether every tile of rect is inside
+    pub fn covers(&self, rect: (i32, i32, i32, i32)) -> bool {
+        (rect.1..rect.3).all(|y| (rect.0..rect.2).all(|x| self.contains((x, y))))
+    }
+
+    //Smallest rect around the tiles of rect that are inside, None if there aren't any
+    pub fn clip(&self, rect: (i32, i32, i32, i32)) -> Option<(i32, i32, i32, i32)> {
+        let cells: Vec<(i32, i32)> = (rect.1..rect.3)
+            .flat_map(|y| (rect.0..rect.2).map(move |x| (x, y)))
+            .filter(|point| self.contains(*point))
+            .collect();
+
+        match cells.is_empty() {
+            true => None,
+            false => Some(DungeonMap::bounding_box(&cells)),
+        }
+    }
+
+    //Biggest rect within rect that's entirely inside, None if no tile of rect is.
+    //Goes row by row keeping how far each column reaches up, and finds the biggest rectangle under that.
+    pub fn largest_rect(&self, rect: (i32, i32, i32, i32)) -> Option<(i32, i32, i32, i32)> {
+        let width = (rect.2 - rect.0).max(0) as usize;
+        let mut heights: Vec<i32> = vec![0; width];
+        let mut best: Option<((i32, i32, i32, i32), i32)> = None;
+
+        for y in rect.1..rect.3 {
+            for (i, height) in heights.iter_mut().enumerate() {
+                *height = match self.contains((rect.0 + i as i32, y)) {
+                    true => *height + 1,
+                    false => 0,
+                };
+            }
+
+            //Columns with rising heights, closed off as soon as a shorter one turns up
+            let mut stack: Vec<usize> = Vec::new();
+            for i in 0..=width {
+                let height = heights.get(i).copied().unwrap_or(0);
+
+                while let Some(top) = stack.last().copied().filter(|top| heights[*top] >= height) {
+                    stack.pop();
+                    let left = stack.last().map_or(0, |left| left + 1);
+                    let area = heights[top] * (i - left) as i32;
+
+                    if area > 0 && best.is_none_or(|(_, best)| area > best) {
+                        let found = (rect.0 + left as i32, y + 1 - heights[top], rect.0 + i as i32, y + 1);
+                        best = Some((found, area));
+                    }
+                }
+                stack.push(i);
+            }
+        }
+
+        best.map(|(rect, _)| rect)
+    }
+}
+
+impl DungeonTree {
+    pub fn mask(&self) -> Option<&Mask> {
+        self.mask.as_ref()
+    }
+
+    //Keeps splitting, rooms and corridors to the tiles inside the mask. Set it before generating,
+    //since it's only looked at while sub-dungeons are split, rooms built and corridors dug.
+    //Floorplans still fill the whole root, as their rooms have to meet.
+    pub fn set_mask(&mut self, mask: Option<Mask>) {
+        self.mask = mask;
+    }
+
+    //Cuts each room down to the biggest rectangle inside the mask, walls included, with a tile of the mask
+    //left around it so corridors can get to every side. Rooms left with no floor are dropped.
+    pub(crate) fn fit_rooms_to_mask(&mut self) {
+        let inner = match &self.mask {
+            Some(mask) => Mask::from_fn(mask.bounds, |point| {
+                (-1..=1).all(|dy| (-1..=1).all(|dx| mask.contains((point.0 + dx, point.1 + dy))))
+            }),
+            None => return,
+        };
+
+        for node in self.nodes.iter_mut().flatten() {
+            if let Some(room) = node.room {
+                node.room = inner.largest_rect(room).filter(|room| DungeonTree::has_floor(*room));
+            }
+        }
+    }
+
+    //The rasterized map with everything outside the mask walled off, for routing corridors on
+    pub(crate) fn routing_map(&self) -> DungeonMap {
+        let mut map = self.rasterize();
+
+        if let Some(mask) = &self.mask {
+            for idx in 0..map.len() {
+                let point = map.point(idx);
+                if !mask.contains(point) {
+                    map.set(point, Tile::Wall);
+                }
+            }
+        }
+
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mask;
+    use crate::map::{Tile, DIRECTIONS};
+    use crate::{split_dirs, Dungeon, TreeError};
+
+    #[test]
+    fn corridors_never_leave_the_mask() {
+        //Two halves with nothing between them, so rooms on opposite sides can't be joined,
+        //and a circle that can be joined up for spurs and the entrance to stay inside
+        let halves = Mask::from_fn((0, 0, 80, 60), |(x, _)| !(36..44).contains(&x));
+        let circle = Mask::circle((40, 30), 29);
+        let mut unroutable = 0;
+        let mut spurs = 0;
+
+        for seed in 0..40 {
+            let mask = match seed % 2 {
+                0 => &halves,
+                _ => &circle,
+            };
+            let mut dungeon = Dungeon::new((0, 0, 80, 60), 4, split_dirs::RANDOM);
+            dungeon.tree.set_seed(seed);
+            dungeon.tree.set_mask(Some(mask.clone()));
+            match dungeon.generate((2, 2, 2, 2)) {
+                Ok(()) => {
+                    spurs += dungeon.tree.add_dead_ends(20, (4, 12)).len();
+                    match dungeon.tree.place_entrance_exit(true) {
+                        Ok(placed) => {
+                            //Out on the edge of the mask rather than the map
+                            let (x, y) = placed.entrance;
+                            assert!(mask.contains((x, y)));
+                            assert!(DIRECTIONS.iter().any(|(dx, dy)| !mask.contains((x + dx, y + dy))), "{:?} not on the edge", (x, y));
+                        }
+                        Err(TreeError::NoRouteError) => {}
+                        Err(err) => panic!("{}", err),
+                    }
+                }
+                Err(TreeError::NoRouteError) => unroutable += 1,
+                Err(err) => panic!("{}", err),
+            }
+
+            let map = dungeon.tree.rasterize();
+            for idx in 0..map.len() {
+                let point = map.point(idx);
+                assert!(map.get(point) == Some(Tile::Empty) || mask.contains(point), "{:?} outside the mask\n{}", point, map.to_ascii());
+            }
+        }
+
+        assert!(unroutable > 0);
+        assert!(spurs > 0);
+    }
+}
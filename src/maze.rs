@@ -0,0 +1,362 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+
+use crate::map::{DungeonMap, Tile, DIRECTIONS};
+use crate::{Dungeon, DungeonPath, DungeonTree, TreeError};
+
+//Cells from a room's wall out, the room's node_id, the other room's node_id if it ends at a room, and the pockets it joins
+type Connector = (Vec<(i32, i32)>, usize, Option<usize>, (usize, usize));
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MazeSettings {
+    //Chance the maze keeps going the way it was heading, 0 to 1. Higher gives long twisting passages,
+    //lower gives short zig-zagging ones.
+    pub windiness: f32,
+    //Chance of a door from a room to something it's already joined to, for loops
+    pub extra_doors: f32,
+    //Share of the dead ends eaten back to the nearest junction, 0 to 1
+    pub dead_end_removal: f32,
+}
+
+impl Default for MazeSettings {
+    fn default() -> MazeSettings {
+        MazeSettings {
+            windiness: 0.7,
+            extra_doors: 0.05,
+            dead_end_removal: 1.0,
+        }
+    }
+}
+
+impl Dungeon {
+    //Like generate, with the space between the rooms filled by fill_with_maze instead of gen_paths
+    pub fn generate_rooms_and_mazes(&mut self, offsets: (i32, i32, i32, i32), settings: &MazeSettings) -> Result<(), TreeError> {
+        self.split_all(offsets)?;
+        self.tree.build_rooms(offsets)?;
+        self.tree.fill_with_maze(settings)
+    }
+}
+
+impl DungeonMap {
+    //Growing tree maze from start, always carrying on from the newest cell. Maze cells are two tiles apart
+    //and can go anywhere can_carve allows, with the tile between two joined cells carved too.
+    //Returns the passages carved, split wherever the maze branches.
+    pub fn grow_maze<R: Rng>(
+        &mut self,
+        start: (i32, i32),
+        tile: Tile,
+        windiness: f32,
+        can_carve: impl Fn((i32, i32)) -> bool,
+        rng: &mut R,
+    ) -> Vec<Vec<(i32, i32)>> {
+        let mut carved: HashSet<(i32, i32)> = HashSet::from([start]);
+        let mut cells: Vec<(i32, i32)> = vec![start];
+        let mut runs: Vec<Vec<(i32, i32)>> = Vec::new();
+        let mut run: Vec<(i32, i32)> = vec![start];
+        let mut last_dir: Option<(i32, i32)> = None;
+
+        self.set(start, tile);
+
+        while let Some(cell) = cells.last().copied() {
+            let unmade: Vec<(i32, i32)> = DIRECTIONS
+                .iter()
+                .copied()
+                .filter(|(dx, dy)| {
+                    let next = (cell.0 + 2 * dx, cell.1 + 2 * dy);
+                    !carved.contains(&next) && can_carve(next)
+                })
+                .collect();
+
+            if unmade.is_empty() {
+                cells.pop();
+                last_dir = None;
+                continue;
+            }
+
+            let dir = match last_dir {
+                Some(last) if unmade.contains(&last) && rng.gen::<f32>() < windiness => last,
+                _ => *unmade.choose(rng).unwrap(),
+            };
+
+            //Picking up from an older cell starts a new branch
+            if run.last() != Some(&cell) {
+                if run.len() > 1 {
+                    runs.push(run);
+                }
+                run = vec![cell];
+            }
+
+            let mid = (cell.0 + dir.0, cell.1 + dir.1);
+            let next = (cell.0 + 2 * dir.0, cell.1 + 2 * dir.1);
+            self.set(mid, tile);
+            self.set(next, tile);
+            run.push(mid);
+            run.push(next);
+
+            carved.insert(next);
+            cells.push(next);
+            last_dir = Some(dir);
+        }
+
+        if run.len() > 1 {
+            runs.push(run);
+        }
+
+        runs
+    }
+}
+
+impl DungeonTree {
+    //Fills the empty space between the rooms with mazes, joins every room to them (or straight to a neighbouring room)
+    //through doors, then eats back dead_end_removal of the dead ends. Maze passages are paths that don't join rooms,
+    //so the room graph only has the doors running straight between two rooms.
+    //build_rooms must have been run first, and gen_paths shouldn't be.
+    pub fn fill_with_maze(&mut self, settings: &MazeSettings) -> Result<(), TreeError> {
+        let mut rng = self.fork_rng();
+        let mut map = self.rasterize();
+        let (x1, y1, x2, y2) = map.bounds;
+
+        //Maze cells sit on odd offsets from the corner, clear of everything around them and inside the mask
+        let open: HashSet<(i32, i32)> = (0..map.len())
+            .map(|idx| map.point(idx))
+            .filter(|point| (point.0 - x1) % 2 == 1 && (point.1 - y1) % 2 == 1)
+            .filter(|point| point.0 > x1 && point.1 > y1 && point.0 < x2 - 1 && point.1 < y2 - 1)
+            .filter(|point| (-1..=1).all(|dy| (-1..=1).all(|dx| map.get((point.0 + dx, point.1 + dy)) == Some(Tile::Empty))))
+            .filter(|point| self.mask.as_ref().is_none_or(|mask| (-1..=1).all(|dy| (-1..=1).all(|dx| mask.contains((point.0 + dx, point.1 + dy))))))
+            .collect();
+
+        let mut starts: Vec<(i32, i32)> = open.iter().copied().collect();
+        starts.sort();
+
+        for start in starts {
+            if map.get(start) != Some(Tile::Empty) {
+                continue;
+            }
+
+            let runs = map.grow_maze(start, Tile::Corridor, settings.windiness, |point| open.contains(&point), &mut rng);
+            self.paths.extend(runs.iter().map(|run| DungeonPath::from_cells(run)));
+        }
+
+        self.add_maze_doors(&mut map, settings.extra_doors, &mut rng)?;
+
+        //Mazes no room opened onto can never be reached
+        let mut removed: HashSet<(i32, i32)> = HashSet::new();
+        for pocket in map.pockets() {
+            if pocket.iter().all(|point| map.get(*point) == Some(Tile::Corridor)) {
+                removed.extend(pocket.iter().copied());
+            }
+        }
+        for point in removed.iter() {
+            map.set(*point, Tile::Empty);
+        }
+
+        //Eat back a share of the dead ends, each one all the way
+        let mut ends = map.dead_ends();
+        ends.shuffle(&mut rng);
+        ends.truncate((ends.len() as f32 * settings.dead_end_removal.clamp(0.0, 1.0)).round() as usize);
+
+        for end in ends {
+            let mut curr = Some(end);
+            while let Some(point) = curr {
+                let walkable: Vec<(i32, i32)> = map.neighbours(point).into_iter().filter(|next| map.is_walkable(*next)).collect();
+                if walkable.len() > 1 {
+                    break;
+                }
+
+                match map.get(point) {
+                    Some(Tile::Corridor) => map.set(point, Tile::Empty),
+                    //A door left opening onto nothing is walled back up
+                    Some(Tile::Door) => {
+                        map.set(point, Tile::Wall);
+                        removed.insert(point);
+                        break;
+                    }
+                    _ => break,
+                }
+                removed.insert(point);
+                curr = walkable.first().copied();
+            }
+        }
+
+        self.remove_path_cells(&removed);
+
+        Ok(())
+    }
+
+    //Every wall tile of a room with the maze or another room a tile or two straight out from it is somewhere a door could go.
+    //Doors go in at random until every room is joined up, then the rest each get extra_doors chance,
+    //never right beside another door unless there's no other way to join something up.
+    //Fails with DisconnectedError if the rooms still can't all be joined.
+    fn add_maze_doors<R: Rng>(&mut self, map: &mut DungeonMap, extra_doors: f32, rng: &mut R) -> Result<(), TreeError> {
+        let rooms: Vec<(usize, (i32, i32, i32, i32))> = self
+            .get_subtree_rooms(0)
+            .iter()
+            .map(|node| (node.node_id, node.room.unwrap()))
+            .collect();
+
+        //Which room's floor or which maze each walkable tile belongs to
+        let mut pocket_of: HashMap<(i32, i32), usize> = HashMap::new();
+        for (idx, pocket) in map.pockets().into_iter().enumerate() {
+            for point in pocket {
+                pocket_of.insert(point, idx);
+            }
+        }
+
+        let room_of_wall = |point: (i32, i32)| {
+            rooms.iter().find(|(_, room)| {
+                let on_side = point.0 > room.0 && point.0 < room.2 - 1 && (point.1 == room.1 || point.1 == room.3 - 1);
+                let on_end = point.1 > room.1 && point.1 < room.3 - 1 && (point.0 == room.0 || point.0 == room.2 - 1);
+                on_side || on_end
+            })
+        };
+
+        let mut connectors: Vec<Connector> = Vec::new();
+
+        for (node_id, room) in rooms.iter() {
+            for y in room.1..room.3 {
+                for x in room.0..room.2 {
+                    let wall = (x, y);
+                    if room_of_wall(wall).map(|(id, _)| *id) != Some(*node_id) {
+                        continue;
+                    }
+
+                    let out = match wall {
+                        _ if y == room.1 => (0, -1),
+                        _ if y == room.3 - 1 => (0, 1),
+                        _ if x == room.0 => (-1, 0),
+                        _ => (1, 0),
+                    };
+                    //Built interiors can have rock right inside the wall
+                    let inner = match pocket_of.get(&(wall.0 - out.0, wall.1 - out.1)) {
+                        Some(inner) => *inner,
+                        None => continue,
+                    };
+
+                    let mut cells = vec![wall];
+                    for step in 1..=3 {
+                        let point = (wall.0 + out.0 * step, wall.1 + out.1 * step);
+                        match map.get(point) {
+                            Some(Tile::Empty) if self.mask.as_ref().is_none_or(|mask| mask.contains(point)) => cells.push(point),
+                            Some(Tile::Corridor) => {
+                                connectors.push((cells, *node_id, None, (inner, pocket_of[&point])));
+                                break;
+                            }
+                            Some(Tile::Wall) => {
+                                let beyond = pocket_of.get(&(point.0 + out.0, point.1 + out.1));
+                                if let (Some((other, _)), Some(beyond)) = (room_of_wall(point), beyond) {
+                                    cells.push(point);
+                                    connectors.push((cells, *node_id, Some(*other), (inner, *beyond)));
+                                }
+                                break;
+                            }
+                            _ => break,
+                        }
+                    }
+                }
+            }
+        }
+
+        connectors.shuffle(rng);
+
+        let mut sets: HashMap<usize, usize> = HashMap::new();
+        let find = |sets: &HashMap<usize, usize>, mut item: usize| {
+            while let Some(parent) = sets.get(&item) {
+                item = *parent;
+            }
+            item
+        };
+        let mut doors: Vec<(i32, i32)> = Vec::new();
+
+        //Joining passes first so extra doors can't use up the spots a room needed. Joins skipped for being beside
+        //another door are tried again, crowded or not, once every other join has had its turn.
+        for (joining, spaced) in [(true, true), (true, false), (false, true)] {
+            for (cells, room, other, (a, b)) in connectors.iter() {
+                let (set_a, set_b) = (find(&sets, *a), find(&sets, *b));
+                let joins = set_a != set_b;
+
+                if joining != joins || (!joins && rng.gen::<f32>() >= extra_doors) {
+                    continue;
+                }
+                let crowded = cells.iter().any(|cell| {
+                    doors.iter().any(|door| (door.0 - cell.0).abs() + (door.1 - cell.1).abs() <= 1)
+                });
+                if crowded && spaced {
+                    continue;
+                }
+
+                if joins {
+                    sets.insert(set_a, set_b);
+                }
+
+                for cell in cells.iter() {
+                    map.carve(*cell);
+                }
+                doors.push(cells[0]);
+                if let Some(last) = other.map(|_| *cells.last().unwrap()) {
+                    doors.push(last);
+                }
+
+                let mut path = DungeonPath::from_cells(cells);
+                path.rooms = other.map(|other| (*room, other));
+                self.paths.push(path);
+            }
+        }
+
+        //Every room's floor has to have ended up in the same set
+        let roots: HashSet<usize> = rooms
+            .iter()
+            .filter_map(|(_, room)| (room.1..room.3).flat_map(|y| (room.0..room.2).map(move |x| (x, y))).find_map(|point| pocket_of.get(&point)))
+            .map(|pocket| find(&sets, *pocket))
+            .collect();
+
+        match roots.len() <= 1 {
+            true => Ok(()),
+            false => Err(TreeError::DisconnectedError),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::MazeSettings;
+    use crate::map::Tile;
+    use crate::{split_dirs, Dungeon, DungeonTree};
+
+    #[test]
+    fn every_room_is_reached_and_the_maze_fills_the_gaps() {
+        for seed in 0..20 {
+            let mut dungeon = Dungeon::new((0, 0, 80, 60), 4, split_dirs::RANDOM);
+            dungeon.tree.set_seed(seed);
+            dungeon.split_all((2, 2, 2, 2)).unwrap();
+            dungeon.tree.build_rooms((2, 2, 2, 2)).unwrap();
+
+            //Spots a maze cell could go before anything is dug
+            let rooms_only = dungeon.tree.rasterize();
+            let open: HashSet<(i32, i32)> = (0..rooms_only.len())
+                .map(|idx| rooms_only.point(idx))
+                .filter(|point| point.0 % 2 == 1 && point.1 % 2 == 1 && point.0 < 79 && point.1 < 59)
+                .filter(|point| (-1..=1).all(|dy| (-1..=1).all(|dx| rooms_only.get((point.0 + dx, point.1 + dy)) == Some(Tile::Empty))))
+                .collect();
+
+            let settings = MazeSettings {
+                dead_end_removal: 0.0,
+                ..MazeSettings::default()
+            };
+            dungeon.tree.fill_with_maze(&settings).unwrap();
+            let map = dungeon.tree.rasterize();
+
+            let centers: Vec<(i32, i32)> = dungeon.tree.get_subtree_rooms(0).iter().map(|node| DungeonTree::room_center(node.room.unwrap())).collect();
+            let distances = map.distances_from(centers[0]);
+            for center in centers.iter() {
+                assert!(distances[map.index(*center).unwrap()].is_some(), "{:?} can't be reached\n{}", center, map.to_ascii());
+            }
+
+            for point in open.iter() {
+                assert_eq!(map.get(*point), Some(Tile::Corridor), "{:?} left out of the maze\n{}", point, map.to_ascii());
+            }
+        }
+    }
+}
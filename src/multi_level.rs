@@ -0,0 +1,220 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashSet, VecDeque};
+
+use crate::{split_dirs, Dungeon, DungeonTree, TreeError};
+
+//How many times a floor is re-generated when it shares no room space with the floor above it
+const STAIR_RETRIES: usize = 10;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Staircase {
+    //Down-stairs are on `level`, up-stairs on `level + 1`, both at `position`
+    pub level: usize,
+    pub position: (i32, i32),
+    //node_id of the room holding the stairs on each floor
+    pub upper_room: usize,
+    pub lower_room: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MultiLevelDungeon {
+    pub levels: Vec<DungeonTree>,
+    pub stairs: Vec<Staircase>,
+}
+
+impl MultiLevelDungeon {
+    //Generates num_levels floors with the same root coords and links every pair of neighbouring floors with stairs.
+    //Each floor is seeded from seed, so the same seed gives the same floors and stairs.
+    pub fn generate(
+        coords: (i32, i32, i32, i32),
+        num_levels: usize,
+        splits: i64,
+        offsets: (i32, i32, i32, i32),
+        seed: u64,
+    ) -> Result<MultiLevelDungeon, TreeError> {
+        let mut seeds = StdRng::seed_from_u64(seed);
+        let mut dungeon = MultiLevelDungeon {
+            levels: Vec::with_capacity(num_levels),
+            stairs: Vec::new(),
+        };
+
+        for level in 0..num_levels {
+            let mut attempts = 0;
+
+            loop {
+                let mut floor = Dungeon::new(coords, splits, split_dirs::RANDOM);
+                floor.tree.set_seed(seeds.gen());
+                floor.generate(offsets)?;
+
+                if level == 0 {
+                    dungeon.levels.push(floor.tree);
+                    break;
+                }
+
+                match dungeon.place_stairs(&dungeon.levels[level - 1], &mut floor.tree, level - 1) {
+                    Some(staircase) => {
+                        dungeon.levels.push(floor.tree);
+                        dungeon.stairs.push(staircase);
+                        break;
+                    }
+                    None => {
+                        attempts += 1;
+                        if attempts >= STAIR_RETRIES {
+                            return Err(TreeError::StairPlacementError);
+                        }
+                    }
+                }
+            }
+        }
+
+        dungeon.validate()?;
+
+        Ok(dungeon)
+    }
+
+    //Picks a random cell that is inside a room on both floors and not already used by other stairs,
+    //drawing from the lower floor's rng
+    fn place_stairs(&self, upper: &DungeonTree, lower: &mut DungeonTree, level: usize) -> Option<Staircase> {
+        let taken: HashSet<(i32, i32)> = self
+            .stairs
+            .iter()
+            .filter(|stairs| stairs.level + 1 == level)
+            .map(|stairs| stairs.position)
+            .collect();
+
+        let mut spots: Vec<(usize, usize, (i32, i32))> = Vec::new();
+
+        for (upper_id, upper_room) in MultiLevelDungeon::room_interiors(upper) {
+            for (lower_id, lower_room) in MultiLevelDungeon::room_interiors(lower) {
+                let x1 = i32::max(upper_room.0, lower_room.0);
+                let y1 = i32::max(upper_room.1, lower_room.1);
+                let x2 = i32::min(upper_room.2, lower_room.2);
+                let y2 = i32::min(upper_room.3, lower_room.3);
+
+                for y in y1..=y2 {
+                    for x in x1..=x2 {
+                        if !taken.contains(&(x, y)) {
+                            spots.push((upper_id, lower_id, (x, y)));
+                        }
+                    }
+                }
+            }
+        }
+
+        if spots.is_empty() {
+            return None;
+        }
+
+        let (upper_room, lower_room, position) = spots[lower.rng.gen_range(0..spots.len())];
+
+        Some(Staircase {
+            level,
+            position,
+            upper_room,
+            lower_room,
+        })
+    }
+
+    //Room floor space inside the walls as (node_id, (x1, y1, x2, y2)), inclusive
+    fn room_interiors(tree: &DungeonTree) -> Vec<(usize, (i32, i32, i32, i32))> {
+        tree.nodes
+            .iter()
+            .filter_map(|node| *node)
+            .filter(|node| node.left.is_none() && node.right.is_none())
+            .filter_map(|node| node.room.map(|room| (node.node_id, room)))
+            .map(|(id, room)| (id, (room.0 + 1, room.1 + 1, room.2 - 2, room.3 - 2)))
+            .filter(|(_, room)| room.0 <= room.2 && room.1 <= room.3)
+            .collect()
+    }
+
+    //Checks every room on every floor can be walked to from the first room on the top floor,
+    //going through paths on a floor and stairs between floors
+    pub fn validate(&self) -> Result<(), TreeError> {
+        
+        let mut all_rooms: HashSet<(usize, usize)> = HashSet::new();
+        for (level, tree) in self.levels.iter().enumerate() {
+            for (room, _) in MultiLevelDungeon::room_interiors(tree) {
+                all_rooms.insert((level, room));
+            }
+        }
+
+        for stairs in self.stairs.iter() {
+            if !all_rooms.contains(&(stairs.level, stairs.upper_room))
+                || !all_rooms.contains(&(stairs.level + 1, stairs.lower_room))
+            {
+                return Err(TreeError::StairPlacementError);
+            }
+        }
+
+        let start = match all_rooms.iter().filter(|(level, _)| *level == 0).min() {
+            Some(start) => *start,
+            None => return Err(TreeError::NoLeavesError),
+        };
+
+        let mut seen: HashSet<(usize, usize)> = HashSet::new();
+        let mut queue: VecDeque<(usize, usize)> = VecDeque::new();
+        seen.insert(start);
+        queue.push_back(start);
+
+        while let Some((level, room)) = queue.pop_front() {
+            let mut next: Vec<(usize, usize)> = self.levels[level]
+                .room_graph()
+                .neighbors(room)
+                .into_iter()
+                .map(|other| (level, other))
+                .collect();
+
+            for stairs in self.stairs.iter() {
+                if stairs.level == level && stairs.upper_room == room {
+                    next.push((level + 1, stairs.lower_room));
+                }
+                if stairs.level + 1 == level && stairs.lower_room == room {
+                    next.push((level - 1, stairs.upper_room));
+                }
+            }
+
+            for other in next {
+                if seen.insert(other) {
+                    queue.push_back(other);
+                }
+            }
+        }
+
+        if all_rooms.is_subset(&seen) {
+            Ok(())
+        } else {
+            Err(TreeError::DisconnectedError)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MultiLevelDungeon;
+    use crate::map::rect_contains;
+
+    #[test]
+    fn stairs_line_up_and_come_out_the_same_for_a_seed() {
+        for seed in 0..6 {
+            let dungeon = MultiLevelDungeon::generate((0, 0, 80, 60), 3, 4, (2, 2, 2, 2), seed).unwrap();
+            assert_eq!(dungeon.stairs.len(), 2);
+
+            for stairs in dungeon.stairs.iter() {
+                //Down on one floor and up on the next at the same point, in the rooms it says
+                for (level, room) in [(stairs.level, stairs.upper_room), (stairs.level + 1, stairs.lower_room)] {
+                    let tree = &dungeon.levels[level];
+                    let rect = tree.nodes[room].and_then(|node| node.room).unwrap();
+                    assert!(rect_contains(rect, stairs.position));
+                    assert!(tree.rasterize().is_walkable(stairs.position), "{:?} on floor {}", stairs.position, level);
+                }
+            }
+
+            let again = MultiLevelDungeon::generate((0, 0, 80, 60), 3, 4, (2, 2, 2, 2), seed).unwrap();
+            assert_eq!(again.stairs, dungeon.stairs);
+            for (floor, other) in dungeon.levels.iter().zip(again.levels.iter()) {
+                assert_eq!(floor.rasterize().to_ascii(), other.rasterize().to_ascii());
+            }
+        }
+    }
+}
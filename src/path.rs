@@ -0,0 +1,229 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::map::brush_cells;
+use crate::{DungeonPath, DungeonTree};
+
+//A straight run of cells as (from, to), both included
+pub type Segment = ((i32, i32), (i32, i32));
+
+impl DungeonPath {
+    //Packs a list of cells into straight segments. Cells next to each other along a line share a segment,
+    //and a cell that isn't next to the one before it starts a new segment.
+    pub fn from_cells(cells: &[(i32, i32)]) -> DungeonPath {
+        let mut segments: Vec<Segment> = Vec::new();
+        let mut iter = cells.iter().copied();
+
+        if let Some(first) = iter.next() {
+            let mut start = first;
+            let mut prev = first;
+            let mut dir: Option<(i32, i32)> = None;
+
+            for cell in iter {
+                let step = (cell.0 - prev.0, cell.1 - prev.1);
+                let adjacent = step.0.abs() + step.1.abs() == 1;
+
+                if adjacent && (dir.is_none() || dir == Some(step)) {
+                    dir = Some(step);
+                } else {
+                    segments.push((start, prev));
+                    //A turn shares its corner with the segment before, a jump starts fresh
+                    start = if adjacent { prev } else { cell };
+                    dir = if adjacent { Some(step) } else { None };
+                }
+                prev = cell;
+            }
+
+            segments.push((start, prev));
+        }
+
+        DungeonPath {
+            segments,
+            rooms: None,
+            width: 1,
+        }
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    //Every cell along the segments in order. Corners shared by two segments come out once.
+    pub fn cells(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        let mut last: Option<(i32, i32)> = None;
+
+        self.segments
+            .iter()
+            .flat_map(|(from, to)| {
+                let step = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+                let count = i32::max((to.0 - from.0).abs(), (to.1 - from.1).abs());
+                (0..=count).map(move |i| (from.0 + step.0 * i, from.1 + step.1 * i))
+            })
+            .filter(move |cell| {
+                let repeat = last == Some(*cell);
+                last = Some(*cell);
+                !repeat
+            })
+    }
+
+    //Every tile the path digs once its width is brushed around each cell, in no particular order
+    pub fn footprint(&self) -> HashSet<(i32, i32)> {
+        self.cells().flat_map(|cell| brush_cells(cell, self.width)).collect()
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.cells().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn start(&self) -> Option<(i32, i32)> {
+        self.segments.first().map(|segment| segment.0)
+    }
+
+    pub fn end(&self) -> Option<(i32, i32)> {
+        self.segments.last().map(|segment| segment.1)
+    }
+
+    //Keeps the same rooms and width, with new cells
+    fn with_cells(&self, cells: &[(i32, i32)]) -> DungeonPath {
+        DungeonPath {
+            rooms: self.rooms,
+            width: self.width,
+            ..DungeonPath::from_cells(cells)
+        }
+    }
+
+    //Cuts out any loop where the path comes back to a cell it already went through,
+    //so every cell is visited once and the path still runs from the same start to the same end
+    pub fn simplify(&mut self) {
+        let mut cells: Vec<(i32, i32)> = Vec::new();
+        let mut seen: HashMap<(i32, i32), usize> = HashMap::new();
+
+        for cell in self.cells() {
+            match seen.get(&cell) {
+                Some(idx) => {
+                    for dropped in cells.drain(idx + 1..) {
+                        seen.remove(&dropped);
+                    }
+                }
+                None => {
+                    seen.insert(cell, cells.len());
+                    cells.push(cell);
+                }
+            }
+        }
+
+        *self = self.with_cells(&cells);
+    }
+
+    //Takes the given cells out of the path, splitting segments around them
+    pub fn remove_cells(&mut self, removed: &HashSet<(i32, i32)>) {
+        let cells: Vec<(i32, i32)> = self.cells().filter(|cell| !removed.contains(cell)).collect();
+        *self = self.with_cells(&cells);
+    }
+}
+
+impl DungeonTree {
+    //Simplifies every path, then trims the runs at either end of later paths that go along an earlier path at least
+    //as wide, keeping the last shared cell so the trimmed path still meets the one it ran along.
+    //Only the ends are trimmed, so every path stays one unbroken run. Overlaps in the middle are left for rasterize
+    //to dig once and show up in the room graph as junctions.
+    //Paths left with no cells are kept, since they still record which rooms are joined.
+    //Returns how many cells were removed.
+    pub fn merge_paths(&mut self) -> usize {
+        let before: usize = self.paths.iter().map(|path| path.cell_count()).sum();
+        //Widest path covering each cell so far
+        let mut covered: HashMap<(i32, i32), i32> = HashMap::new();
+
+        for path in self.paths.iter_mut() {
+            path.simplify();
+
+            let cells: Vec<(i32, i32)> = path.cells().collect();
+            let shared = |cell: &(i32, i32)| covered.get(cell).is_some_and(|width| *width >= path.width);
+            let lead = cells.iter().take_while(|cell| shared(cell)).count();
+
+            if lead == cells.len() {
+                *path = path.with_cells(&[]);
+            } else {
+                let tail = cells.iter().rev().take_while(|cell| shared(cell)).count();
+                let kept = &cells[lead.saturating_sub(1)..(cells.len() - tail + 1).min(cells.len())];
+                *path = path.with_cells(kept);
+            }
+
+            for cell in path.cells() {
+                let width = covered.entry(cell).or_insert(path.width);
+                *width = (*width).max(path.width);
+            }
+        }
+        self.update_room_graph();
+
+        before - self.paths.iter().map(|path| path.cell_count()).sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use crate::map::DIRECTIONS;
+    use crate::{split_dirs, Dungeon, DungeonPath};
+
+    #[test]
+    fn cells_come_back_out_as_they_went_in() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..200 {
+            let mut cells: Vec<(i32, i32)> = vec![(rng.gen_range(0..20), rng.gen_range(0..20))];
+            for _ in 0..rng.gen_range(0..40) {
+                let last = *cells.last().unwrap();
+                //Mostly steps, with the odd jump to somewhere not next to the last cell
+                let next = match rng.gen_range(0..8) {
+                    0 => (last.0 + rng.gen_range(2..6), last.1 - rng.gen_range(2..6)),
+                    _ => {
+                        let (dx, dy) = DIRECTIONS[rng.gen_range(0..4)];
+                        (last.0 + dx, last.1 + dy)
+                    }
+                };
+                cells.push(next);
+            }
+
+            let path = DungeonPath::from_cells(&cells);
+            assert_eq!(path.cells().collect::<Vec<_>>(), cells);
+            assert_eq!(path.start(), cells.first().copied());
+            assert_eq!(path.end(), cells.last().copied());
+        }
+
+        assert!(DungeonPath::from_cells(&[]).is_empty());
+    }
+
+    #[test]
+    fn merging_keeps_paths_whole_and_digs_the_same_tiles() {
+        for width in [1, 2, 3].into_iter().cycle().take(60) {
+            let mut dungeon = Dungeon::new((0, 0, 80, 60), 4, split_dirs::RANDOM);
+            dungeon.split_all((2, 2, 2, 2)).unwrap();
+            dungeon.tree.build_rooms((2, 2, 2, 2)).unwrap();
+            dungeon.tree.set_corridor_width(width);
+            dungeon.tree.gen_paths().unwrap();
+            let _ = dungeon.tree.add_loops(0.6);
+
+            let before = dungeon.tree.rasterize();
+            dungeon.tree.merge_paths();
+            let after = dungeon.tree.rasterize();
+
+            for idx in 0..before.len() {
+                let point = before.point(idx);
+                assert_eq!(before.is_walkable(point), after.is_walkable(point), "{:?}\n{}\n{}", point, before.to_ascii(), after.to_ascii());
+            }
+
+            for path in dungeon.tree.paths.iter() {
+                let cells: Vec<(i32, i32)> = path.cells().collect();
+                for pair in cells.windows(2) {
+                    let step = (pair[1].0 - pair[0].0).abs() + (pair[1].1 - pair[0].1).abs();
+                    assert_eq!(step, 1, "gap between {:?} and {:?}", pair[0], pair[1]);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use rand::seq::SliceRandom;
+
+use crate::{DungeonTree, TreeError};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionGraph {
+    //Delaunay triangulation of the room centers
+    Delaunay,
+    //Pairs of rooms with no other room center inside the circle that has them as its diameter.
+    //A subset of Delaunay, so it gives fewer, more local connections.
+    Gabriel,
+}
+
+impl DungeonTree {
+    //Plans which rooms to connect from a graph over the room centers instead of the tree's siblings.
+    //A minimum spanning tree of the graph keeps every room reachable, then extra_ratio of the graph's
+    //other edges (0 to 1) are picked at random and added back for loops.
+    pub fn plan_connections(&mut self, graph: ConnectionGraph, extra_ratio: f32) -> Result<Vec<(usize, usize)>, TreeError> {
+        let rooms: Vec<(usize, (f64, f64))> = self
+            .get_subtree_rooms(0)
+            .iter()
+            .map(|node| {
+                let center = DungeonTree::room_center(node.room.unwrap());
+                (node.node_id, (center.0 as f64, center.1 as f64))
+            })
+            .collect();
+
+        if rooms.is_empty() {
+            return Err(TreeError::NoLeavesError);
+        }
+
+        let points: Vec<(f64, f64)> = rooms.iter().map(|(_, center)| *center).collect();
+
+        //Gabriel edges are always Delaunay edges too, and cover point sets too flat to triangulate
+        let mut edges = gabriel_edges(&points);
+        if graph == ConnectionGraph::Delaunay {
+            for edge in delaunay_edges(&points) {
+                if !edges.contains(&edge) {
+                    edges.push(edge);
+                }
+            }
+        }
+
+        let length = |edge: &(usize, usize)| {
+            let (a, b) = (points[edge.0], points[edge.1]);
+            (a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)
+        };
+        edges.sort_by(|a, b| length(a).total_cmp(&length(b)));
+
+        //Kruskal's, with the skipped edges kept aside for loops
+        let mut sets: Vec<usize> = (0..points.len()).collect();
+        let mut chosen: Vec<(usize, usize)> = Vec::new();
+        let mut spare: Vec<(usize, usize)> = Vec::new();
+
+        for edge in edges {
+            let a = find_set(&mut sets, edge.0);
+            let b = find_set(&mut sets, edge.1);
+
+            if a != b {
+                sets[a] = b;
+                chosen.push(edge);
+            } else {
+                spare.push(edge);
+            }
+        }
+
+        let extra = (spare.len() as f32 * extra_ratio.clamp(0.0, 1.0)).round() as usize;
+        spare.shuffle(&mut self.rng);
+        chosen.extend(spare.into_iter().take(extra));
+
+        Ok(chosen.into_iter().map(|(a, b)| (rooms[a].0, rooms[b].0)).collect())
+    }
+
+    //Like gen_paths, but with the connections from plan_connections
+    pub fn gen_planned_paths(&mut self, graph: ConnectionGraph, extra_ratio: f32) -> Result<(), TreeError> {
+        let connections = self.plan_connections(graph, extra_ratio)?;
+        self.connect_rooms(&connections)?;
+
+        Ok(())
+    }
+}
+
+pub(crate) fn find_set(sets: &mut [usize], item: usize) -> usize {
+    let mut root = item;
+    while sets[root] != root {
+        root = sets[root];
+    }
+
+    let mut curr = item;
+    while sets[curr] != root {
+        let next = sets[curr];
+        sets[curr] = root;
+        curr = next;
+    }
+
+    root
+}
+
+fn gabriel_edges(points: &[(f64, f64)]) -> Vec<(usize, usize)> {
+    let mut edges: Vec<(usize, usize)> = Vec::new();
+
+    for a in 0..points.len() {
+        for b in a + 1..points.len() {
+            let mid = ((points[a].0 + points[b].0) / 2.0, (points[a].1 + points[b].1) / 2.0);
+            let radius = (points[a].0 - mid.0).powi(2) + (points[a].1 - mid.1).powi(2);
+
+            let blocked = (0..points.len()).any(|c| {
+                c != a && c != b && (points[c].0 - mid.0).powi(2) + (points[c].1 - mid.1).powi(2) <= radius
+            });
+
+            if !blocked {
+                edges.push((a, b));
+            }
+        }
+    }
+
+    edges
+}
+
+//Bowyer-Watson, returning each edge once as (lower index, higher index)
+fn delaunay_edges(points: &[(f64, f64)]) -> Vec<(usize, usize)> {
+    if points.len() < 3 {
+        return Vec::new();
+    }
+
+    let min_x = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+    let min_y = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+    let max_x = points.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+    let max_y = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+    let span = (max_x - min_x).max(max_y - min_y).max(1.0) * 20.0;
+    let mid = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+
+    //The super triangle's corners go after the real points
+    let mut all: Vec<(f64, f64)> = points.to_vec();
+    all.push((mid.0 - span, mid.1 - span));
+    all.push((mid.0 + span, mid.1 - span));
+    all.push((mid.0, mid.1 + span));
+    let n = points.len();
+
+    let mut triangles: Vec<[usize; 3]> = vec![[n, n + 1, n + 2]];
+
+    for idx in 0..n {
+        let point = all[idx];
+        let (bad, good): (Vec<[usize; 3]>, Vec<[usize; 3]>) = triangles
+            .into_iter()
+            .partition(|tri| in_circumcircle(&all, tri, point));
+        triangles = good;
+
+        //Edges of the hole left by the bad triangles are the ones only one of them has
+        let mut boundary: Vec<(usize, usize)> = Vec::new();
+        for tri in bad.iter() {
+            for edge in [(tri[0], tri[1]), (tri[1], tri[2]), (tri[2], tri[0])] {
+                let shared = bad.iter().filter(|other| other.contains(&edge.0) && other.contains(&edge.1)).count() > 1;
+                if !shared {
+                    boundary.push(edge);
+                }
+            }
+        }
+
+        for (a, b) in boundary {
+            triangles.push([a, b, idx]);
+        }
+    }
+
+    let mut edges: Vec<(usize, usize)> = Vec::new();
+    for tri in triangles.iter().filter(|tri| tri.iter().all(|corner| *corner < n)) {
+        for (a, b) in [(tri[0], tri[1]), (tri[1], tri[2]), (tri[2], tri[0])] {
+            let edge = (usize::min(a, b), usize::max(a, b));
+            if !edges.contains(&edge) {
+                edges.push(edge);
+            }
+        }
+    }
+
+    edges
+}
+
+fn in_circumcircle(points: &[(f64, f64)], tri: &[usize; 3], point: (f64, f64)) -> bool {
+    let (a, b, c) = (points[tri[0]], points[tri[1]], points[tri[2]]);
+    let d = 2.0 * (a.0 * (b.1 - c.1) + b.0 * (c.1 - a.1) + c.0 * (a.1 - b.1));
+
+    //Flat triangles have no circumcircle
+    if d.abs() < f64::EPSILON {
+        return false;
+    }
+
+    let a_sq = a.0 * a.0 + a.1 * a.1;
+    let b_sq = b.0 * b.0 + b.1 * b.1;
+    let c_sq = c.0 * c.0 + c.1 * c.1;
+    let center = (
+        (a_sq * (b.1 - c.1) + b_sq * (c.1 - a.1) + c_sq * (a.1 - b.1)) / d,
+        (a_sq * (c.0 - b.0) + b_sq * (a.0 - c.0) + c_sq * (b.0 - a.0)) / d,
+    );
+    let radius = (a.0 - center.0).powi(2) + (a.1 - center.1).powi(2);
+
+    (point.0 - center.0).powi(2) + (point.1 - center.1).powi(2) < radius
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{delaunay_edges, find_set, gabriel_edges, ConnectionGraph};
+    use crate::{split_dirs, Dungeon};
+
+    #[test]
+    fn gabriel_edges_are_the_delaunay_edges_with_nothing_in_their_circle() {
+        //A square with a point just below its middle, which sits inside the circle on the bottom side
+        let points = [(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0), (5.0, 4.0)];
+
+        let mut delaunay = delaunay_edges(&points);
+        delaunay.sort();
+        assert_eq!(delaunay, vec![(0, 1), (0, 3), (0, 4), (1, 2), (1, 4), (2, 3), (2, 4), (3, 4)]);
+
+        let mut gabriel = gabriel_edges(&points);
+        gabriel.sort();
+        assert_eq!(gabriel, vec![(0, 3), (0, 4), (1, 2), (1, 4), (2, 3), (2, 4), (3, 4)]);
+    }
+
+    #[test]
+    fn planned_connections_span_every_room() {
+        for (seed, graph) in (0..20).zip([ConnectionGraph::Delaunay, ConnectionGraph::Gabriel].into_iter().cycle()) {
+            let mut dungeon = Dungeon::new((0, 0, 80, 60), 4, split_dirs::RANDOM);
+            dungeon.tree.set_seed(seed);
+            dungeon.split_all((2, 2, 2, 2)).unwrap();
+            dungeon.tree.build_rooms((2, 2, 2, 2)).unwrap();
+
+            let rooms: Vec<usize> = dungeon.tree.get_subtree_rooms(0).iter().map(|node| node.node_id).collect();
+            let index = |room: usize| rooms.iter().position(|id| *id == room).unwrap();
+
+            //With no extra edges it's a spanning tree, so one fewer edge than rooms and every room joined up
+            let tree = dungeon.tree.plan_connections(graph, 0.0).unwrap();
+            assert_eq!(tree.len(), rooms.len() - 1);
+
+            let mut sets: Vec<usize> = (0..rooms.len()).collect();
+            for (a, b) in tree.iter() {
+                let (a, b) = (find_set(&mut sets, index(*a)), find_set(&mut sets, index(*b)));
+                assert_ne!(a, b, "spanning tree has a loop");
+                sets[a] = b;
+            }
+
+            //Extra edges only ever add to it
+            let all = dungeon.tree.plan_connections(graph, 1.0).unwrap();
+            assert!(tree.iter().all(|edge| all.contains(edge)));
+
+            dungeon.tree.gen_planned_paths(graph, 0.3).unwrap();
+            let map = dungeon.tree.rasterize();
+            let distances = map.distances_from(map.regions()[0].center().unwrap());
+            for region in map.regions() {
+                assert!(distances[map.index(region.center().unwrap()).unwrap()].is_some(), "room {} cut off", region.id);
+            }
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::map::Tile;
+use crate::{DungeonTree, TreeError};
+
+//(room node_id, role, floor tiles) of somewhere spawns can go
+type Region = (Option<usize>, RoomRole, Vec<(i32, i32)>);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoomRole {
+    Entrance,
+    Exit,
+    //Holds a key from place_locks
+    Key,
+    Normal,
+    Corridor,
+    //A corridor spur from add_dead_ends
+    DeadEnd,
+}
+
+impl RoomRole {
+    //Names used in spawn table files
+    pub fn from_name(name: &str) -> Option<RoomRole> {
+        match name {
+            "entrance" => Some(RoomRole::Entrance),
+            "exit" => Some(RoomRole::Exit),
+            "key" => Some(RoomRole::Key),
+            "normal" => Some(RoomRole::Normal),
+            "corridor" => Some(RoomRole::Corridor),
+            "dead-end" => Some(RoomRole::DeadEnd),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpawnEntry {
+    pub entity_id: String,
+    pub weight: u32,
+    //Lowest dungeon depth (floor number for a MultiLevelDungeon) this can appear on
+    pub min_depth: usize,
+    pub max_per_room: usize,
+    pub roles: Vec<RoomRole>,
+    //Lowest danger (see DangerMap) of a tile this can appear on
+    pub min_danger: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpawnTable {
+    pub entries: Vec<SpawnEntry>,
+    //Spawns attempted per walkable tile of a room or corridor, e.g. 0.02 is one per 50 tiles (rounded up)
+    pub density: f32,
+}
+
+impl SpawnTable {
+    //Reads a table with one entry per line as "entity_id weight min_depth max_per_room min_danger roles...",
+    //with roles named as in RoomRole::from_name, and an optional "density = 0.02" line, which is the default.
+    //Blank lines and ones starting with # are skipped.
+    pub fn parse(text: &str) -> Result<SpawnTable, TreeError> {
+        let mut table = SpawnTable {
+            entries: Vec::new(),
+            density: 0.02,
+        };
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let bad = || TreeError::SpawnTableError(line.to_string());
+
+            if let Some((key, value)) = line.split_once('=') {
+                match key.trim() {
+                    "density" => table.density = value.trim().parse().map_err(|_| bad())?,
+                    _ => return Err(bad()),
+                }
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 {
+                return Err(bad());
+            }
+
+            table.entries.push(SpawnEntry {
+                entity_id: fields[0].to_string(),
+                weight: fields[1].parse().map_err(|_| bad())?,
+                min_depth: fields[2].parse().map_err(|_| bad())?,
+                max_per_room: fields[3].parse().map_err(|_| bad())?,
+                min_danger: fields[4].parse().map_err(|_| bad())?,
+                roles: fields[5..].iter().map(|name| RoomRole::from_name(name).ok_or_else(bad)).collect::<Result<_, _>>()?,
+            });
+        }
+
+        Ok(table)
+    }
+
+    pub fn load(path: &Path) -> Result<SpawnTable, TreeError> {
+        let text = fs::read_to_string(path).map_err(|err| TreeError::SpawnTableReadError(err.to_string()))?;
+
+        SpawnTable::parse(&text)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spawn {
+    pub entity_id: String,
+    pub position: (i32, i32),
+    //node_id of the room, None for corridors
+    pub room: Option<usize>,
+    //Danger of the tile it was placed on, for rolling loot rarity and the like
+    pub danger: f32,
+}
+
+//"entity_id x y", as written next to the map by draw_to_file and the command line
+impl fmt::Display for Spawn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.entity_id, self.position.0, self.position.1)
+    }
+}
+
+impl DungeonTree {
+    //Spawns from the last populate
+    pub fn spawns(&self) -> &[Spawn] {
+        &self.spawns
+    }
+
+    //Role of a room given what has been placed in it so far
+    pub fn room_role(&self, node_id: usize) -> RoomRole {
+        if let Some(placed) = &self.entrance_exit {
+            if placed.entrance_room == node_id {
+                return RoomRole::Entrance;
+            }
+            if placed.exit_room == node_id {
+                return RoomRole::Exit;
+            }
+        }
+
+        if let Some(progression) = &self.progression {
+            if progression.keys.iter().any(|key| key.room == node_id) {
+                return RoomRole::Key;
+            }
+        }
+
+        RoomRole::Normal
+    }
+
+    //Places spawns from the table on the floor of every room and corridor.
+    //Without a placed entrance every tile has a danger of 0.
+    //The same tree, table, depth and seed always give the same spawns.
+    pub fn populate(&mut self, table: &SpawnTable, depth: usize, seed: u64) -> Result<Vec<Spawn>, TreeError> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let map = self.rasterize();
+        let danger = self.danger_map().ok();
+
+        //Tiles already holding something the player needs to get to
+        let mut taken: HashSet<(i32, i32)> = HashSet::new();
+        if let Some(placed) = &self.entrance_exit {
+            taken.insert(placed.entrance);
+            taken.insert(placed.exit);
+        }
+        if let Some(progression) = &self.progression {
+            taken.extend(progression.keys.iter().map(|key| key.position));
+            taken.extend(progression.doors.iter().map(|door| door.position));
+        }
+
+        //Every room then every corridor, in a fixed order
+        let mut regions: Vec<Region> = Vec::new();
+
+        let mut rooms: Vec<(usize, (i32, i32, i32, i32))> = self
+            .get_leaves()?
+            .iter()
+            .filter_map(|node| *node)
+            .filter_map(|node| node.room.map(|room| (node.node_id, room)))
+            .collect();
+        rooms.sort_by_key(|(node_id, _)| *node_id);
+
+        for (node_id, (x1, y1, x2, y2)) in rooms {
+            let mut tiles: Vec<(i32, i32)> = Vec::new();
+            for y in y1..y2 {
+                for x in x1..x2 {
+                    if map.get((x, y)) == Some(Tile::Floor) {
+                        tiles.push((x, y));
+                    }
+                }
+            }
+            regions.push((Some(node_id), self.room_role(node_id), tiles));
+        }
+
+        let mut seen: HashSet<(i32, i32)> = HashSet::new();
+        for path in self.paths.iter() {
+            let tiles: Vec<(i32, i32)> = path
+                .cells()
+                .filter(|point| map.get(*point) == Some(Tile::Corridor))
+                .filter(|point| seen.insert(*point))
+                .collect();
+            let role = match tiles.iter().any(|tile| self.dead_ends.contains(tile)) {
+                true => RoomRole::DeadEnd,
+                false => RoomRole::Corridor,
+            };
+            regions.push((None, role, tiles));
+        }
+
+        let mut spawns: Vec<Spawn> = Vec::new();
+
+        for (room, role, tiles) in regions {
+            let mut free: Vec<(i32, i32)> = tiles.into_iter().filter(|tile| !taken.contains(tile)).collect();
+            let attempts = (free.len() as f32 * table.density).ceil() as usize;
+            let mut counts: Vec<usize> = vec![0; table.entries.len()];
+
+            for _ in 0..attempts {
+                if free.is_empty() {
+                    break;
+                }
+
+                let position = free.swap_remove(rng.gen_range(0..free.len()));
+                let tile_danger = danger.as_ref().and_then(|danger| danger.at(position)).unwrap_or(0.0);
+
+                let eligible: Vec<usize> = (0..table.entries.len())
+                    .filter(|idx| {
+                        let entry = &table.entries[*idx];
+                        entry.weight > 0
+                            && entry.min_depth <= depth
+                            && entry.min_danger <= tile_danger
+                            && entry.roles.contains(&role)
+                            && counts[*idx] < entry.max_per_room
+                    })
+                    .collect();
+
+                if eligible.is_empty() {
+                    continue;
+                }
+
+                let total: u32 = eligible.iter().map(|idx| table.entries[*idx].weight).sum();
+                let mut roll = rng.gen_range(0..total);
+                let mut chosen = eligible[0];
+                for idx in eligible {
+                    if roll < table.entries[idx].weight {
+                        chosen = idx;
+                        break;
+                    }
+                    roll -= table.entries[idx].weight;
+                }
+
+                counts[chosen] += 1;
+
+                spawns.push(Spawn {
+                    entity_id: table.entries[chosen].entity_id.clone(),
+                    position,
+                    room,
+                    danger: tile_danger,
+                });
+            }
+        }
+
+        self.spawns = spawns.clone();
+
+        Ok(spawns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{RoomRole, SpawnTable};
+    use crate::generator::GeneratorRegistry;
+    use crate::{split_dirs, Dungeon, TreeError};
+
+    #[test]
+    fn spawn_tables_are_parsed() {
+        let table = SpawnTable::parse("# goblins\ndensity = 0.05\ngoblin 3 0 2 0.0 normal corridor dead-end\nchest 1 0 1 0.0 entrance exit key normal\n").unwrap();
+        assert_eq!(table.density, 0.05);
+        assert_eq!(table.entries[0].roles, vec![RoomRole::Normal, RoomRole::Corridor, RoomRole::DeadEnd]);
+        assert_eq!(table.entries[1].max_per_room, 1);
+
+        for bad in ["goblin 3 0 2 0.0", "goblin 3 0 2 0.0 attic", "colour = red"] {
+            assert!(matches!(SpawnTable::parse(bad), Err(TreeError::SpawnTableError(_))), "{:?} was accepted", bad);
+        }
+    }
+
+    #[test]
+    fn spawns_keep_to_their_depth_danger_and_room_limits() {
+        let table = SpawnTable::parse("density = 1.0\ndeep 1 3 50 0.0 normal corridor\nfierce 1 0 50 0.7 normal corridor\nlone 5 0 1 0.0 normal\n").unwrap();
+        let mut deep_placed = 0;
+
+        for seed in 0..10 {
+            let mut dungeon = Dungeon::new((0, 0, 80, 60), 4, split_dirs::RANDOM);
+            dungeon.tree.set_seed(seed);
+            dungeon.generate((2, 2, 2, 2)).unwrap();
+            dungeon.tree.place_entrance_exit(false).unwrap();
+
+            for depth in [2, 3] {
+                let spawns = dungeon.tree.populate(&table, depth, seed).unwrap();
+                let mut per_room: HashMap<usize, usize> = HashMap::new();
+
+                for spawn in spawns.iter() {
+                    match spawn.entity_id.as_str() {
+                        "deep" => {
+                            assert!(depth >= 3, "deep spawned at depth {}", depth);
+                            deep_placed += 1;
+                        }
+                        "fierce" => assert!(spawn.danger >= 0.7, "fierce spawned at danger {}", spawn.danger),
+                        _ => *per_room.entry(spawn.room.unwrap()).or_insert(0) += 1,
+                    }
+                }
+                assert!(per_room.values().all(|count| *count <= 1), "{:?}", per_room);
+            }
+        }
+
+        assert!(deep_placed > 0);
+    }
+
+    #[test]
+    fn populated_builtins_carry_their_spawns() {
+        let table = SpawnTable::parse("density = 0.05\ngoblin 3 0 2 0.0 normal corridor dead-end\nchest 1 0 1 0.0 entrance exit key normal\n").unwrap();
+
+        let registry = GeneratorRegistry::with_populated_builtins(&table);
+        for name in ["bsp", "scatter"] {
+            for seed in 0..4 {
+                let generated = registry.generate(name, (0, 0, 80, 50), seed).unwrap();
+                assert!(!generated.spawns.is_empty(), "{} with seed {} placed nothing", name, seed);
+                for spawn in generated.spawns.iter() {
+                    assert!(generated.map.is_walkable(spawn.position), "{} spawned off the floor", spawn);
+                }
+                assert_eq!(registry.generate(name, (0, 0, 80, 50), seed).unwrap().spawns, generated.spawns);
+            }
+        }
+    }
+}
@@ -0,0 +1,511 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+use crate::map::{brush_cells, rect_contains, DIRECTIONS};
+use crate::DungeonTree;
+
+//(node_id, room) of a room with floor
+pub(crate) type PlacedRoom = (usize, (i32, i32, i32, i32));
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoomEdge {
+    //Index into DungeonTree::paths of the corridor
+    pub path_idx: usize,
+    //node_id of the rooms at each end
+    pub rooms: (usize, usize),
+    //Cells in the corridor
+    pub length: usize,
+    //Cells where the corridor goes through the wall of one of its own rooms
+    pub doors: Vec<(i32, i32)>,
+    //lock_id of the locked door on this corridor, if place_locks put one here
+    pub locked: Option<usize>,
+}
+
+//Somewhere a corridor meets something it wasn't dug to reach
+#[derive(Clone, Debug, PartialEq)]
+pub struct Junction {
+    //For a crossing, the run of corridor tiles shared by the paths or right beside each other.
+    //For a room passed through, the cells of the path digging into the room, walls included.
+    pub cells: Vec<(i32, i32)>,
+    //Indices into DungeonTree::paths of every path meeting here
+    pub paths: Vec<usize>,
+    //node_id of the room a path passes through, None for corridors crossing or overlapping
+    pub room: Option<usize>,
+}
+
+//A place in the room graph: a room by node_id, or a crossing by its index in RoomGraph::junctions.
+//A room passed through is just the room, since that's where the path leads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum GraphNode {
+    Room(usize),
+    Junction(usize),
+}
+
+//A stretch of one path between two places it reaches, with nothing else met along the way.
+//Paths are cut into links at every room and crossing they touch, and walks step from link to link.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Link {
+    //Index into DungeonTree::paths
+    pub path_idx: usize,
+    pub ends: (GraphNode, GraphNode),
+    //Cells walked along the path from one end to the other
+    pub length: usize,
+}
+
+//Rooms by node_id, joined by the corridors between them
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RoomGraph {
+    rooms: Vec<usize>,
+    edges: Vec<RoomEdge>,
+    junctions: Vec<Junction>,
+    links: Vec<Link>,
+}
+
+impl RoomGraph {
+    //For generators that lay out their own rooms instead of a tree's leaves. Junctions are left empty,
+    //and each edge is a single link between its rooms.
+    pub(crate) fn from_edges(rooms: Vec<usize>, edges: Vec<RoomEdge>) -> RoomGraph {
+        let links = edges
+            .iter()
+            .map(|edge| Link {
+                path_idx: edge.path_idx,
+                ends: (GraphNode::Room(edge.rooms.0), GraphNode::Room(edge.rooms.1)),
+                length: edge.length,
+            })
+            .collect();
+
+        RoomGraph {
+            rooms,
+            edges,
+            junctions: Vec::new(),
+            links,
+        }
+    }
+
+    pub fn rooms(&self) -> &[usize] {
+        &self.rooms
+    }
+
+    pub fn edges(&self) -> &[RoomEdge] {
+        &self.edges
+    }
+
+    pub fn junctions(&self) -> &[Junction] {
+        &self.junctions
+    }
+
+    pub fn links(&self) -> &[Link] {
+        &self.links
+    }
+
+    //Junctions the path at path_idx is part of
+    pub fn junctions_on(&self, path_idx: usize) -> Vec<&Junction> {
+        self.junctions.iter().filter(|junction| junction.paths.contains(&path_idx)).collect()
+    }
+
+    //Edge for the corridor at path_idx, if it joins two rooms
+    pub fn edge_for_path(&self, path_idx: usize) -> Option<&RoomEdge> {
+        self.edges.iter().find(|edge| edge.path_idx == path_idx)
+    }
+
+    //Every edge dug to reach room, with the room at its other end
+    pub fn edges_of(&self, room: usize) -> Vec<(usize, &RoomEdge)> {
+        self.edges
+            .iter()
+            .filter_map(|edge| match edge.rooms {
+                (a, b) if a == room => Some((b, edge)),
+                (a, b) if b == room => Some((a, edge)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    //Every link touching node, with the node at its other end
+    pub fn links_of(&self, node: GraphNode) -> Vec<(GraphNode, &Link)> {
+        self.links
+            .iter()
+            .filter_map(|link| match link.ends {
+                (a, b) if a == node => Some((b, link)),
+                (a, b) if b == node => Some((a, link)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    //Rooms that can be walked to from room without going through another room, crossings included, each listed once
+    pub fn neighbors(&self, room: usize) -> Vec<usize> {
+        let mut neighbors: Vec<usize> = Vec::new();
+        let mut seen: HashSet<GraphNode> = HashSet::from([GraphNode::Room(room)]);
+        let mut queue: VecDeque<GraphNode> = VecDeque::from([GraphNode::Room(room)]);
+
+        while let Some(node) = queue.pop_front() {
+            for (next, _) in self.links_of(node) {
+                if !seen.insert(next) {
+                    continue;
+                }
+                match next {
+                    GraphNode::Room(other) => neighbors.push(other),
+                    GraphNode::Junction(_) => queue.push_back(next),
+                }
+            }
+        }
+
+        neighbors.sort();
+        neighbors
+    }
+
+    //Links touching room. A corridor passing through the room counts once going in and once coming out.
+    pub fn degree(&self, room: usize) -> usize {
+        self.links_of(GraphNode::Room(room)).len()
+    }
+
+    //Breadth first walk from the start room over links, through rooms and crossings alike,
+    //that won't step along any path whose index is in blocked.
+    //Returns the parent of every node reached as (parent, path index).
+    pub fn walk(&self, start: usize, blocked: &HashSet<usize>) -> HashMap<GraphNode, Option<(GraphNode, usize)>> {
+        let mut parents: HashMap<GraphNode, Option<(GraphNode, usize)>> = HashMap::new();
+        let mut queue: VecDeque<GraphNode> = VecDeque::new();
+
+        parents.insert(GraphNode::Room(start), None);
+        queue.push_back(GraphNode::Room(start));
+
+        while let Some(node) = queue.pop_front() {
+            for (next, link) in self.links_of(node) {
+                if blocked.contains(&link.path_idx) || parents.contains_key(&next) {
+                    continue;
+                }
+                parents.insert(next, Some((node, link.path_idx)));
+                queue.push_back(next);
+            }
+        }
+
+        parents
+    }
+
+    //Rooms and crossings on the shortest walk from one room to another by corridor length, both ends included
+    pub fn shortest_route(&self, from: usize, to: usize) -> Option<Vec<GraphNode>> {
+        let (from, to) = (GraphNode::Room(from), GraphNode::Room(to));
+        let mut best: HashMap<GraphNode, usize> = HashMap::new();
+        let mut parents: HashMap<GraphNode, GraphNode> = HashMap::new();
+        let mut queue: BinaryHeap<Reverse<(usize, GraphNode)>> = BinaryHeap::new();
+
+        best.insert(from, 0);
+        queue.push(Reverse((0, from)));
+
+        while let Some(Reverse((dist, node))) = queue.pop() {
+            if node == to {
+                let mut route = vec![to];
+                let mut curr = to;
+                while let Some(parent) = parents.get(&curr) {
+                    route.push(*parent);
+                    curr = *parent;
+                }
+                route.reverse();
+                return Some(route);
+            }
+            if dist > best[&node] {
+                continue;
+            }
+
+            for (next, link) in self.links_of(node) {
+                let next_dist = dist + link.length;
+                if best.get(&next).is_none_or(|old| next_dist < *old) {
+                    best.insert(next, next_dist);
+                    parents.insert(next, node);
+                    queue.push(Reverse((next_dist, next)));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl DungeonTree {
+    pub fn room_graph(&self) -> &RoomGraph {
+        &self.room_graph
+    }
+
+    //Whether rasterize puts doors where a corridor goes through the wall of a room it wasn't dug to reach.
+    //Off by default, leaving a plain gap in the wall.
+    pub fn set_pass_through_doors(&mut self, doors: bool) {
+        self.pass_through_doors = doors;
+    }
+
+    //Rebuilds room_graph from the rooms, paths and locked doors as they are now.
+    //Anything that adds, removes or reorders paths calls this.
+    pub(crate) fn update_room_graph(&mut self) {
+        let rooms: Vec<PlacedRoom> = self
+            .get_subtree_rooms(0)
+            .iter()
+            .map(|node| (node.node_id, node.room.unwrap()))
+            .collect();
+
+        let on_wall = |cell: (i32, i32), ends: (usize, usize)| {
+            rooms.iter().filter(|(node_id, _)| *node_id == ends.0 || *node_id == ends.1).any(|(_, room)| {
+                rect_contains(*room, cell) && (cell.0 == room.0 || cell.0 == room.2 - 1 || cell.1 == room.1 || cell.1 == room.3 - 1)
+            })
+        };
+
+        let edges: Vec<RoomEdge> = self
+            .paths
+            .iter()
+            .enumerate()
+            .filter_map(|(path_idx, path)| {
+                let rooms = path.rooms?;
+                let locked = self
+                    .progression
+                    .as_ref()
+                    .and_then(|progression| progression.doors.iter().find(|door| door.path_idx == path_idx))
+                    .map(|door| door.lock_id);
+
+                Some(RoomEdge {
+                    path_idx,
+                    rooms,
+                    length: path.cell_count(),
+                    doors: path.cells().filter(|cell| on_wall(*cell, rooms)).collect(),
+                    locked,
+                })
+            })
+            .collect();
+
+        let junctions = self.find_junctions(&rooms);
+        let links = self.find_links(&rooms, &junctions);
+
+        self.room_graph = RoomGraph {
+            rooms: rooms.iter().map(|(node_id, _)| *node_id).collect(),
+            edges,
+            junctions,
+            links,
+        };
+    }
+
+    //Corridor tiles outside every room that more than one path digs, or that one path digs right beside another's,
+    //grouped into touching runs, then every room a path digs into without being one of the rooms it joins.
+    //Tiles dug are the whole brush of a wide corridor, not just the cells down its middle.
+    fn find_junctions(&self, rooms: &[PlacedRoom]) -> Vec<Junction> {
+        let mut users: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (path_idx, path) in self.paths.iter().enumerate() {
+            for cell in path.footprint() {
+                if rooms.iter().any(|(_, room)| rect_contains(*room, cell)) {
+                    continue;
+                }
+                let paths = users.entry(cell).or_default();
+                if !paths.contains(&path_idx) {
+                    paths.push(path_idx);
+                }
+            }
+        }
+
+        //Paths digging a tile or one beside it, since corridors side by side are as open to each other as ones crossing
+        let meeting = |cell: (i32, i32)| {
+            let mut paths: Vec<usize> = users[&cell].clone();
+            for (dx, dy) in DIRECTIONS {
+                for path_idx in users.get(&(cell.0 + dx, cell.1 + dy)).into_iter().flatten() {
+                    if !paths.contains(path_idx) {
+                        paths.push(*path_idx);
+                    }
+                }
+            }
+            paths
+        };
+        let shared: HashMap<(i32, i32), Vec<usize>> = users
+            .keys()
+            .map(|cell| (*cell, meeting(*cell)))
+            .filter(|(_, paths)| paths.len() > 1)
+            .collect();
+
+        let mut starts: Vec<(i32, i32)> = shared.keys().copied().collect();
+        starts.sort();
+
+        let mut junctions: Vec<Junction> = Vec::new();
+        let mut grouped: HashSet<(i32, i32)> = HashSet::new();
+
+        for first in starts.iter() {
+            if !grouped.insert(*first) {
+                continue;
+            }
+
+            let mut cells: Vec<(i32, i32)> = vec![*first];
+            let mut paths: Vec<usize> = Vec::new();
+            let mut queue: VecDeque<(i32, i32)> = VecDeque::from([*first]);
+
+            while let Some(cell) = queue.pop_front() {
+                for path_idx in shared[&cell].iter() {
+                    if !paths.contains(path_idx) {
+                        paths.push(*path_idx);
+                    }
+                }
+
+                for (dx, dy) in DIRECTIONS {
+                    let next = (cell.0 + dx, cell.1 + dy);
+                    if shared.contains_key(&next) && grouped.insert(next) {
+                        cells.push(next);
+                        queue.push_back(next);
+                    }
+                }
+            }
+
+            paths.sort();
+            junctions.push(Junction { cells, paths, room: None });
+        }
+
+        for (path_idx, path) in self.paths.iter().enumerate() {
+            for (node_id, room) in rooms.iter() {
+                if path.rooms.is_some_and(|(a, b)| a == *node_id || b == *node_id) {
+                    continue;
+                }
+
+                let cells: Vec<(i32, i32)> = path
+                    .cells()
+                    .filter(|cell| brush_cells(*cell, path.width).any(|dug| rect_contains(*room, dug)))
+                    .collect();
+                if !cells.is_empty() {
+                    junctions.push(Junction {
+                        cells,
+                        paths: vec![path_idx],
+                        room: Some(*node_id),
+                    });
+                }
+            }
+        }
+
+        junctions
+    }
+
+    //Cuts every path into links at each room and crossing its brush reaches, in the order it reaches them.
+    //Somewhere a path reaches two places at once links them to each other with a single step.
+    fn find_links(&self, rooms: &[PlacedRoom], junctions: &[Junction]) -> Vec<Link> {
+        let mut crossings: HashMap<(i32, i32), usize> = HashMap::new();
+        for (idx, junction) in junctions.iter().enumerate().filter(|(_, junction)| junction.room.is_none()) {
+            crossings.extend(junction.cells.iter().map(|cell| (*cell, idx)));
+        }
+
+        let mut links: Vec<Link> = Vec::new();
+        for (path_idx, path) in self.paths.iter().enumerate() {
+            let mut linked: HashSet<(GraphNode, GraphNode)> = HashSet::new();
+            //The last place reached and the step it was last touched on
+            let mut current: Option<(GraphNode, usize)> = None;
+
+            for (step, cell) in path.cells().enumerate() {
+                let dug: Vec<(i32, i32)> = brush_cells(cell, path.width).collect();
+                let mut here: Vec<GraphNode> = rooms
+                    .iter()
+                    .filter(|(_, room)| dug.iter().any(|tile| rect_contains(*room, *tile)))
+                    .map(|(node_id, _)| GraphNode::Room(*node_id))
+                    .chain(dug.iter().filter_map(|tile| crossings.get(tile)).map(|idx| GraphNode::Junction(*idx)))
+                    .collect();
+                here.sort();
+                here.dedup();
+
+                if let Some((node, _)) = current {
+                    if here.contains(&node) {
+                        current = Some((node, step));
+                        here.retain(|other| *other != node);
+                    }
+                }
+
+                for node in here {
+                    if let Some((last, left)) = current {
+                        if linked.insert((last.min(node), last.max(node))) {
+                            links.push(Link {
+                                path_idx,
+                                ends: (last, node),
+                                length: (step - left).max(1),
+                            });
+                        }
+                    }
+                    current = Some((node, step));
+                }
+            }
+        }
+
+        links
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{GraphNode, Junction};
+    use crate::{split_dirs, Dungeon, DungeonNode, DungeonPath, DungeonTree};
+
+    #[test]
+    fn walks_reach_the_rooms_the_map_does() {
+        for (width, loops) in [(1, 0.0), (3, 0.0), (1, 0.6), (3, 0.6)].into_iter().cycle().take(60) {
+            let mut dungeon = Dungeon::new((0, 0, 80, 60), 4, split_dirs::RANDOM);
+            dungeon.split_all((2, 2, 2, 2)).unwrap();
+            dungeon.tree.build_rooms((2, 2, 2, 2)).unwrap();
+            dungeon.tree.set_corridor_width(width);
+            dungeon.tree.gen_paths().unwrap();
+            if loops > 0.0 {
+                let _ = dungeon.tree.add_loops(loops);
+            }
+
+            let map = dungeon.tree.rasterize();
+            let graph = dungeon.tree.room_graph();
+            let centers: Vec<(usize, (i32, i32))> = map.regions().iter().filter_map(|region| Some((region.id, region.center()?))).collect();
+            let (start, from) = centers[0];
+            let distances = map.distances_from(from);
+            let parents = graph.walk(start, &HashSet::new());
+
+            for (room, center) in centers.iter() {
+                let on_map = distances[map.index(*center).unwrap()].is_some();
+                assert_eq!(parents.contains_key(&GraphNode::Room(*room)), on_map, "room {} from {}\n{}", room, start, map.to_ascii());
+                assert_eq!(graph.shortest_route(start, *room).is_some(), on_map);
+            }
+        }
+    }
+
+    #[test]
+    fn neighbors_are_found_through_crossings() {
+        //North to south and west to east cross in the middle, and a fifth room hangs off the west one
+        let (north, south, west, east, far) = ((34, 2, 46, 10), (34, 30, 46, 38), (2, 16, 12, 24), (68, 16, 78, 24), (2, 30, 12, 38));
+        let mut tree = DungeonTree::new(3);
+        tree.setRoot(DungeonNode {
+            coords: Some((0, 0, 80, 40)),
+            ..DungeonNode::new()
+        })
+        .unwrap();
+        tree.build_from_rooms(&[north, south, west, east, far]).unwrap();
+
+        let rooms = tree.get_subtree_rooms(0);
+        let id = |rect| rooms.iter().find(|node| node.room == Some(rect)).unwrap().node_id;
+        let straight = |from: (i32, i32), to: (i32, i32), joins: (usize, usize)| {
+            let cells: Vec<(i32, i32)> = match from.0 == to.0 {
+                true => (from.1..=to.1).map(|y| (from.0, y)).collect(),
+                false => (from.0..=to.0).map(|x| (x, from.1)).collect(),
+            };
+            DungeonPath {
+                rooms: Some(joins),
+                ..DungeonPath::from_cells(&cells)
+            }
+        };
+        tree.paths = vec![
+            straight((40, 9), (40, 30), (id(north), id(south))),
+            straight((11, 20), (68, 20), (id(west), id(east))),
+            straight((6, 23), (6, 30), (id(west), id(far))),
+        ];
+        tree.update_room_graph();
+
+        let graph = tree.room_graph();
+        let crossings: Vec<&Junction> = graph.junctions().iter().filter(|junction| junction.room.is_none()).collect();
+        assert_eq!(crossings.len(), 1);
+        assert_eq!(crossings[0].paths, vec![0, 1]);
+        assert!(crossings[0].cells.contains(&(40, 20)));
+
+        let expected = [
+            (north, vec![south, west, east]),
+            (south, vec![north, west, east]),
+            (west, vec![north, south, east, far]),
+            (east, vec![north, south, west]),
+            (far, vec![west]),
+        ];
+        for (room, neighbors) in expected {
+            let mut neighbors: Vec<usize> = neighbors.into_iter().map(id).collect();
+            neighbors.sort();
+            assert_eq!(graph.neighbors(id(room)), neighbors, "neighbours of {:?}", room);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::map::{brush_cells, rect_contains, DungeonMap, Tile};
+use crate::{DungeonPath, DungeonTree, TreeError};
+
+//Cost of stepping onto each kind of tile. Walls are never stepped on, other than the two end points.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RouteCosts {
+    pub empty: u32,
+    pub corridor: u32,
+    pub door: u32,
+    pub floor: u32,
+}
+
+impl Default for RouteCosts {
+    fn default() -> RouteCosts {
+        RouteCosts {
+            empty: 4,
+            corridor: 1,
+            door: 1,
+            floor: 20,
+        }
+    }
+}
+
+impl RouteCosts {
+    fn cost(&self, tile: Tile) -> Option<u32> {
+        match tile {
+            Tile::Empty => Some(self.empty),
+            Tile::Corridor => Some(self.corridor),
+            Tile::Door => Some(self.door),
+            Tile::Floor => Some(self.floor),
+            Tile::Wall => None,
+        }
+    }
+
+    fn cheapest(&self) -> u32 {
+        [self.empty, self.corridor, self.door, self.floor].into_iter().min().unwrap().max(1)
+    }
+}
+
+impl DungeonMap {
+    //Cheapest 4-connected route from one point to another, both included
+    pub fn find_route(&self, from: (i32, i32), to: (i32, i32), costs: &RouteCosts) -> Option<Vec<(i32, i32)>> {
+        self.find_wide_route(from, to, costs, 1, &[])
+    }
+
+    //Like find_route, for a corridor dug width tiles across. A cell is never stepped on if the brush around it
+    //would reach a wall outside every rect in own, so the corridor can't open up the wall of a room it isn't joining.
+    pub fn find_wide_route(
+        &self,
+        from: (i32, i32),
+        to: (i32, i32),
+        costs: &RouteCosts,
+        width: i32,
+        own: &[(i32, i32, i32, i32)],
+    ) -> Option<Vec<(i32, i32)>> {
+        let foreign_wall = |cell: (i32, i32)| self.get(cell) == Some(Tile::Wall) && !own.iter().any(|rect| rect_contains(*rect, cell));
+        let blocked: Vec<bool> = (0..self.len())
+            .map(|idx| brush_cells(self.point(idx), width).any(foreign_wall))
+            .collect();
+
+        let start = self.index(from)?;
+        let goal = self.index(to)?;
+        let step = costs.cheapest();
+        let heuristic = |point: (i32, i32)| ((point.0 - to.0).abs() + (point.1 - to.1).abs()) as u32 * step;
+
+        let mut best: Vec<Option<u32>> = vec![None; self.len()];
+        let mut came_from: Vec<Option<usize>> = vec![None; self.len()];
+        let mut open: BinaryHeap<Reverse<(u32, u32, usize)>> = BinaryHeap::new();
+
+        best[start] = Some(0);
+        open.push(Reverse((heuristic(from), 0, start)));
+
+        while let Some(Reverse((_, cost, idx))) = open.pop() {
+            if idx == goal {
+                let mut route: Vec<(i32, i32)> = vec![self.point(goal)];
+                let mut curr = goal;
+                while let Some(prev) = came_from[curr] {
+                    route.push(self.point(prev));
+                    curr = prev;
+                }
+                route.reverse();
+                return Some(route);
+            }
+
+            if best[idx].is_some_and(|known| cost > known) {
+                continue;
+            }
+
+            for next in self.neighbours(self.point(idx)) {
+                let next_idx = self.index(next).unwrap();
+                if blocked[next_idx] && next_idx != goal {
+                    continue;
+                }
+                let step_cost = match next_idx == goal {
+                    true => costs.door,
+                    false => match costs.cost(self.get(next).unwrap()) {
+                        Some(step_cost) => step_cost,
+                        None => continue,
+                    },
+                };
+
+                let next_cost = cost + step_cost;
+                if best[next_idx].is_none_or(|known| next_cost < known) {
+                    best[next_idx] = Some(next_cost);
+                    came_from[next_idx] = Some(idx);
+                    open.push(Reverse((next_cost + heuristic(next), next_cost, next_idx)));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl DungeonTree {
+    //Spot on the room's wall facing toward, away from the corners so it opens onto the floor
+    pub fn door_point(room: (i32, i32, i32, i32), toward: (i32, i32)) -> Option<(i32, i32)> {
+        let (x1, y1, x2, y2) = room;
+
+        if !DungeonTree::has_floor(room) {
+            return None;
+        }
+
+        let center = DungeonTree::room_center(room);
+        let dx = toward.0 - center.0;
+        let dy = toward.1 - center.1;
+
+        let point = if dx.abs() >= dy.abs() {
+            let y = toward.1.clamp(y1 + 1, y2 - 2);
+            match dx >= 0 {
+                true => (x2 - 1, y),
+                false => (x1, y),
+            }
+        } else {
+            let x = toward.0.clamp(x1 + 1, x2 - 2);
+            match dy >= 0 {
+                true => (x, y2 - 1),
+                false => (x, y1),
+            }
+        };
+
+        Some(point)
+    }
+
+    //Routes a corridor between two rooms with A*, from a door in one to a door in the other.
+    //It never goes through the walls of any room, so other rooms can only be crossed through their existing doors,
+    //and those only if they're already as wide as the corridor.
+    pub fn route_path(&self, map: &DungeonMap, room_a: usize, room_b: usize, costs: &RouteCosts) -> Result<DungeonPath, TreeError> {
+        let rect_a = self.nodes[room_a].and_then(|node| node.room).ok_or(TreeError::RoomIsNoneError)?;
+        let rect_b = self.nodes[room_b].and_then(|node| node.room).ok_or(TreeError::RoomIsNoneError)?;
+
+        let door_a = DungeonTree::door_point(rect_a, DungeonTree::room_center(rect_b)).ok_or(TreeError::NoRouteError)?;
+        let door_b = DungeonTree::door_point(rect_b, DungeonTree::room_center(rect_a)).ok_or(TreeError::NoRouteError)?;
+
+        let width = self.get_connection_width(room_a, room_b);
+        let route = map.find_wide_route(door_a, door_b, costs, width, &[rect_a, rect_b]).ok_or(TreeError::NoRouteError)?;
+
+        Ok(DungeonPath {
+            rooms: Some((room_a, room_b)),
+            width,
+            ..DungeonPath::from_cells(&route)
+        })
+    }
+
+    //Same connections as gen_paths, routed with A* so that corridors never cut through unrelated rooms.
+    //Each corridor is routed on a map holding the ones before it, so later corridors reuse earlier ones.
+    //Nothing outside the mask is stepped on, if there is one.
+    pub fn gen_routed_paths(&mut self, costs: &RouteCosts) -> Result<(), TreeError> {
+        let mut map = self.routing_map();
+
+        for (left, right) in self.get_connections()? {
+            let pth = self.route_path(&map, left, right, costs)?;
+
+            for point in pth.cells() {
+                map.carve_brush(point, pth.width);
+            }
+
+            self.paths.push(pth);
+        }
+        self.update_room_graph();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::map::{rect_contains, DungeonMap, Tile};
+    use crate::{split_dirs, Dungeon};
+
+    //Walled rooms drawn onto an empty map
+    fn rooms_map(bounds: (i32, i32, i32, i32), rooms: &[(i32, i32, i32, i32)]) -> DungeonMap {
+        let mut map = DungeonMap::new(bounds);
+        for (x1, y1, x2, y2) in rooms.iter().copied() {
+            for y in y1..y2 {
+                for x in x1..x2 {
+                    let wall = x == x1 || y == y1 || x == x2 - 1 || y == y2 - 1;
+                    map.set((x, y), if wall { Tile::Wall } else { Tile::Floor });
+                }
+            }
+        }
+        map
+    }
+
+    #[test]
+    fn routes_go_around_rooms_in_the_way() {
+        let (left, middle, right) = ((2, 6, 10, 14), (16, 2, 24, 18), (30, 6, 38, 14));
+        let map = rooms_map((0, 0, 40, 20), &[left, middle, right]);
+
+        let route = map.find_route((9, 10), (30, 10), &Default::default()).unwrap();
+        assert_eq!((route[0], route[route.len() - 1]), ((9, 10), (30, 10)));
+        assert!(route.iter().all(|cell| !rect_contains(middle, *cell)), "route went through the room in the way");
+        for pair in route.windows(2) {
+            assert_eq!((pair[0].0 - pair[1].0).abs() + (pair[0].1 - pair[1].1).abs(), 1);
+        }
+
+        //With the room in the way reaching right across the map there's nowhere left to go
+        let map = rooms_map((0, 0, 40, 20), &[left, (16, 0, 24, 20), right]);
+        assert_eq!(map.find_route((9, 10), (30, 10), &Default::default()), None);
+    }
+
+    #[test]
+    fn wide_corridors_only_open_the_walls_of_their_own_rooms() {
+        for seed in 0..50 {
+            let mut dungeon = Dungeon::new((0, 0, 80, 60), 4, split_dirs::RANDOM);
+            dungeon.tree.set_seed(seed);
+            dungeon.split_all((2, 2, 2, 2)).unwrap();
+            dungeon.tree.build_rooms((2, 2, 2, 2)).unwrap();
+            dungeon.tree.set_corridor_width(3);
+            dungeon.tree.gen_routed_paths(&Default::default()).unwrap();
+
+            let map = dungeon.tree.rasterize();
+            for node in dungeon.tree.get_subtree_rooms(0) {
+                let (x1, y1, x2, y2) = node.room.unwrap();
+                let walls = (y1..y2).flat_map(|y| (x1..x2).map(move |x| (x, y))).filter(|(x, y)| *x == x1 || *y == y1 || *x == x2 - 1 || *y == y2 - 1);
+
+                for wall in walls.filter(|wall| map.get(*wall) != Some(Tile::Wall)) {
+                    let dug_by_own = dungeon.tree.paths.iter().any(|path| {
+                        path.rooms.is_some_and(|(a, b)| a == node.node_id || b == node.node_id) && path.footprint().contains(&wall)
+                    });
+                    assert!(dug_by_own, "{:?} opened by a corridor not joining room {}", wall, node.node_id);
+                }
+            }
+        }
+    }
+}
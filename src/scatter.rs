@@ -0,0 +1,239 @@
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::f64::consts::TAU;
+
+use crate::planner::ConnectionGraph;
+use crate::{Dungeon, DungeonNode, DungeonTree, TreeError};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScatterSettings {
+    //Rooms dropped into the circle before any are thrown away
+    pub room_count: usize,
+    //Radius of the circle the rooms start in, around the middle of the dungeon
+    pub radius: i32,
+    //Smallest and largest width and height of a room, walls included
+    pub room_size: (i32, i32),
+    //Empty tiles kept between the walls of two rooms
+    pub spacing: i32,
+    //Rooms with at least this many times the average area are main rooms. The rest are thrown away.
+    pub main_ratio: f32,
+    //Separation steps before rooms still overlapping are thrown away
+    pub max_steps: usize,
+    //Graph the main rooms are joined over, with extra_ratio of its spare edges added back for loops
+    pub graph: ConnectionGraph,
+    pub extra_ratio: f32,
+}
+
+impl Default for ScatterSettings {
+    fn default() -> ScatterSettings {
+        ScatterSettings {
+            room_count: 40,
+            radius: 15,
+            room_size: (4, 14),
+            spacing: 1,
+            main_ratio: 1.25,
+            max_steps: 500,
+            graph: ConnectionGraph::Delaunay,
+            extra_ratio: 0.15,
+        }
+    }
+}
+
+impl Dungeon {
+    //Alternative to generate that doesn't split the dungeon at all. Rooms are scattered in a circle,
+    //pushed apart until none overlap, and the biggest kept and joined with plan_connections.
+    //The tree is rebuilt with one leaf per main room, so everything run on a generated tree works the same.
+    pub fn generate_scattered(&mut self, settings: &ScatterSettings) -> Result<(), TreeError> {
+        let mut rng = self.tree.fork_rng();
+        let bounds = self.tree.nodes[0].unwrap().coords.unwrap();
+        let center = ((bounds.0 + bounds.2) / 2, (bounds.1 + bounds.3) / 2);
+
+        let (low, high) = (settings.room_size.0.max(3), settings.room_size.1.max(settings.room_size.0.max(3)));
+        //Average of two rolls, so middling rooms are the most common
+        let roll = |rng: &mut StdRng| (rng.gen_range(low..=high) + rng.gen_range(low..=high)) / 2;
+
+        let mut rooms: Vec<(i32, i32, i32, i32)> = (0..settings.room_count)
+            .map(|_| {
+                let angle = rng.gen::<f64>() * TAU;
+                let dist = settings.radius.max(0) as f64 * rng.gen::<f64>().sqrt();
+                let (width, height) = (roll(&mut rng), roll(&mut rng));
+                let x1 = center.0 + (angle.cos() * dist).round() as i32 - width / 2;
+                let y1 = center.1 + (angle.sin() * dist).round() as i32 - height / 2;
+                (x1, y1, x1 + width, y1 + height)
+            })
+            .collect();
+
+        DungeonTree::separate(&mut rooms, settings.spacing, settings.max_steps, &mut rng);
+
+        rooms.retain(|room| room.0 >= bounds.0 && room.1 >= bounds.1 && room.2 <= bounds.2 && room.3 <= bounds.3);
+        if let Some(mask) = &self.tree.mask {
+            rooms.retain(|room| mask.covers(*room));
+        }
+        if rooms.is_empty() {
+            return Err(TreeError::NoLeavesError);
+        }
+
+        let area = |room: &(i32, i32, i32, i32)| ((room.2 - room.0) * (room.3 - room.1)) as f32;
+        let mean = rooms.iter().map(area).sum::<f32>() / rooms.len() as f32;
+        let threshold = mean * settings.main_ratio;
+
+        //The biggest room is always kept so there's something to join
+        rooms.sort_by(|a, b| area(b).total_cmp(&area(a)));
+        let keep = 1 + rooms.iter().skip(1).filter(|room| area(room) >= threshold).count();
+        rooms.truncate(keep);
+
+        self.tree.build_from_rooms(&rooms)?;
+        self.tree.gen_planned_paths(settings.graph, settings.extra_ratio)
+    }
+}
+
+impl DungeonTree {
+    //Steers overlapping rooms away from each other a tile at a time until none overlap with spacing around them.
+    //Rooms still overlapping after max_steps are thrown away, smallest first.
+    fn separate<R: Rng>(rooms: &mut Vec<(i32, i32, i32, i32)>, spacing: i32, max_steps: usize, rng: &mut R) {
+        let overlaps = |a: &(i32, i32, i32, i32), b: &(i32, i32, i32, i32)| {
+            a.0 < b.2 + spacing && b.0 < a.2 + spacing && a.1 < b.3 + spacing && b.1 < a.3 + spacing
+        };
+        let center = |room: &(i32, i32, i32, i32)| ((room.0 + room.2) as f64 / 2.0, (room.1 + room.3) as f64 / 2.0);
+
+        for _ in 0..max_steps {
+            let moves: Vec<(i32, i32)> = rooms
+                .iter()
+                .enumerate()
+                .map(|(i, room)| {
+                    let (mut push, mut crowded) = ((0.0, 0.0), false);
+                    for (j, other) in rooms.iter().enumerate() {
+                        if i != j && overlaps(room, other) {
+                            let (a, b) = (center(room), center(other));
+                            push = (push.0 + a.0 - b.0, push.1 + a.1 - b.1);
+                            crowded = true;
+                        }
+                    }
+
+                    //Rooms sharing a center get shoved any which way
+                    if crowded && push == (0.0, 0.0) {
+                        let angle = rng.gen::<f64>() * TAU;
+                        push = (angle.cos(), angle.sin());
+                    }
+
+                    let length = f64::hypot(push.0, push.1);
+                    match length > 0.0 {
+                        true => ((push.0 / length).round() as i32, (push.1 / length).round() as i32),
+                        false => (0, 0),
+                    }
+                })
+                .collect();
+
+            if moves.iter().all(|step| *step == (0, 0)) {
+                return;
+            }
+
+            for (room, (dx, dy)) in rooms.iter_mut().zip(moves) {
+                *room = (room.0 + dx, room.1 + dy, room.2 + dx, room.3 + dy);
+            }
+        }
+
+        rooms.sort_by_key(|room| -(room.2 - room.0) * (room.3 - room.1));
+        let mut kept: Vec<(i32, i32, i32, i32)> = Vec::new();
+        for room in rooms.iter() {
+            if !kept.iter().any(|other| overlaps(room, other)) {
+                kept.push(*room);
+            }
+        }
+        *rooms = kept;
+    }
+
+    //Replaces everything under the root with a leaf for each room, keeping the root's coords.
+    //Rooms are split in half across their wider spread at each level, like the sub-dungeons of a split,
+    //and each parent's coords are the box around the rooms under it.
+    pub fn build_from_rooms(&mut self, rooms: &[(i32, i32, i32, i32)]) -> Result<(), TreeError> {
+        let root = self.nodes.first().copied().flatten().ok_or(TreeError::NoLeavesError)?;
+        if rooms.is_empty() {
+            return Err(TreeError::NoLeavesError);
+        }
+
+        self.nodes.clear();
+        self.setRoot(root)?;
+        self.place_rooms(0, rooms.to_vec());
+        self.nodes[0].as_mut().unwrap().coords = root.coords;
+        self.update_room_graph();
+
+        Ok(())
+    }
+
+    fn place_rooms(&mut self, idx: usize, mut rooms: Vec<(i32, i32, i32, i32)>) {
+        if self.nodes.len() <= idx {
+            self.nodes.resize(idx + 1, None);
+        }
+
+        let coords = (
+            rooms.iter().map(|room| room.0).min().unwrap(),
+            rooms.iter().map(|room| room.1).min().unwrap(),
+            rooms.iter().map(|room| room.2).max().unwrap(),
+            rooms.iter().map(|room| room.3).max().unwrap(),
+        );
+        let mut node = DungeonNode {
+            coords: Some(coords),
+            node_id: idx,
+            ..DungeonNode::new()
+        };
+
+        if rooms.len() == 1 {
+            node.room = Some(rooms[0]);
+            self.nodes[idx] = Some(node);
+            return;
+        }
+
+        let center = |room: &(i32, i32, i32, i32)| (room.0 + room.2, room.1 + room.3);
+        match coords.2 - coords.0 >= coords.3 - coords.1 {
+            true => rooms.sort_by_key(|room| center(room).0),
+            false => rooms.sort_by_key(|room| center(room).1),
+        }
+        let right = rooms.split_off(rooms.len() / 2);
+
+        node.left = Some(2 * idx + 1);
+        node.right = Some(2 * idx + 2);
+        self.nodes[idx] = Some(node);
+
+        self.place_rooms(2 * idx + 1, rooms);
+        self.place_rooms(2 * idx + 2, right);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScatterSettings;
+    use crate::{split_dirs, Dungeon, DungeonTree};
+
+    #[test]
+    fn scattered_rooms_never_overlap() {
+        for (seed, room_count) in (0..20).zip([10, 40, 1].into_iter().cycle()) {
+            let settings = ScatterSettings {
+                room_count,
+                spacing: 1 + seed as i32 % 2,
+                //Keep every room that survives separating, so the count can be checked
+                main_ratio: 0.0,
+                ..ScatterSettings::default()
+            };
+            let mut dungeon = Dungeon::new((0, 0, 80, 60), 0, split_dirs::RANDOM);
+            dungeon.tree.set_seed(seed);
+            dungeon.generate_scattered(&settings).unwrap();
+
+            let rooms: Vec<(i32, i32, i32, i32)> = dungeon.tree.get_subtree_rooms(0).iter().map(|node| node.room.unwrap()).collect();
+            assert!(!rooms.is_empty() && rooms.len() <= room_count);
+            for (i, a) in rooms.iter().enumerate() {
+                assert!(a.0 >= 0 && a.1 >= 0 && a.2 <= 80 && a.3 <= 60, "{:?} outside the dungeon", a);
+                for b in rooms[i + 1..].iter() {
+                    let apart = a.0 >= b.2 + settings.spacing || b.0 >= a.2 + settings.spacing || a.1 >= b.3 + settings.spacing || b.1 >= a.3 + settings.spacing;
+                    assert!(apart, "{:?} and {:?} closer than {}", a, b, settings.spacing);
+                }
+            }
+
+            let map = dungeon.tree.rasterize();
+            let distances = map.distances_from(DungeonTree::room_center(rooms[0]));
+            for room in rooms.iter() {
+                assert!(distances[map.index(DungeonTree::room_center(*room)).unwrap()].is_some(), "{:?} can't be reached\n{}", room, map.to_ascii());
+            }
+        }
+    }
+}
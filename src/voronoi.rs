@@ -0,0 +1,302 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, VecDeque};
+
+use crate::map::{rect_contains, DungeonMap, Region, Tile, DIRECTIONS};
+use crate::planner::find_set;
+use crate::room_graph::{RoomEdge, RoomGraph};
+use crate::{DungeonPath, TreeError};
+
+//Tiles either side of the border between two cells, as (tile in the first cell, tile in the second)
+type Border = Vec<((i32, i32), (i32, i32))>;
+
+//Splits the bounds into the Voronoi cells of points scattered over it. Each cell is shrunk into a room,
+//and rooms whose cells touch can be joined by a corridor across the border between them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoronoiGenerator {
+    //Points scattered, one per room. Points whose cell is too small for floor are dropped.
+    pub sites: usize,
+    //Tiles between a room's floor and the edge of its cell
+    pub gap: i32,
+    //Share of the neighbouring rooms left unjoined by the spanning tree that get a corridor anyway, 0 to 1
+    pub extra_ratio: f32,
+}
+
+impl Default for VoronoiGenerator {
+    fn default() -> VoronoiGenerator {
+        VoronoiGenerator {
+            sites: 12,
+            gap: 1,
+            extra_ratio: 0.2,
+        }
+    }
+}
+
+//Everything generate_dungeon lays out. Rooms are the map's regions, and the room graph's node ids are region ids
+//with each edge's path_idx pointing into paths.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoronoiDungeon {
+    pub map: DungeonMap,
+    pub paths: Vec<DungeonPath>,
+    pub room_graph: RoomGraph,
+}
+
+impl VoronoiGenerator {
+    //The same seed gives the same dungeon
+    pub fn generate_dungeon(&self, bounds: (i32, i32, i32, i32), seed: u64) -> Result<VoronoiDungeon, TreeError> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut map = DungeonMap::new(bounds);
+        let inner = (bounds.0 + 1, bounds.1 + 1, bounds.2 - 1, bounds.3 - 1);
+
+        if inner.0 >= inner.2 || inner.1 >= inner.3 {
+            return Err(TreeError::EmptyMapError);
+        }
+
+        let mut sites: Vec<(i32, i32)> = (0..self.sites)
+            .map(|_| (rng.gen_range(inner.0..inner.2), rng.gen_range(inner.1..inner.3)))
+            .collect();
+        sites.sort();
+        sites.dedup();
+
+        //Drop the smallest cell with no room for floor and hand it to its neighbours, until every cell has floor.
+        //One at a time, since the neighbours growing can make space for floor in the others.
+        let (owner, floors) = loop {
+            let owner = VoronoiGenerator::partition(&map, &sites);
+            let floors = self.floors(&map, &owner, sites.len(), inner);
+
+            let mut sizes: Vec<usize> = vec![0; sites.len()];
+            for site in owner.iter() {
+                sizes[*site] += 1;
+            }
+
+            match (0..sites.len()).filter(|site| floors[*site].is_empty()).min_by_key(|site| sizes[*site]) {
+                Some(site) if sites.len() > 1 => sites.remove(site),
+                Some(_) => return Err(TreeError::EmptyMapError),
+                None => break (owner, floors),
+            };
+        };
+
+        for floor in floors.iter() {
+            for point in floor.iter() {
+                map.set(*point, Tile::Floor);
+            }
+        }
+        map.wall_in();
+
+        //Every pair of touching cells and the border between them
+        let mut borders: HashMap<(usize, usize), Border> = HashMap::new();
+        for idx in 0..map.len() {
+            let point = map.point(idx);
+            for next in [(point.0 + 1, point.1), (point.0, point.1 + 1)] {
+                let (a, b) = match map.index(next) {
+                    Some(next_idx) => (owner[idx], owner[next_idx]),
+                    None => continue,
+                };
+                if !rect_contains(inner, point) || !rect_contains(inner, next) || a == b {
+                    continue;
+                }
+
+                match a < b {
+                    true => borders.entry((a, b)).or_default().push((point, next)),
+                    false => borders.entry((b, a)).or_default().push((next, point)),
+                }
+            }
+        }
+
+        let distance = |(a, b): &(usize, usize)| {
+            let (a, b) = (sites[*a], sites[*b]);
+            (a.0 - b.0).pow(2) + (a.1 - b.1).pow(2)
+        };
+        let mut pairs: Vec<(usize, usize)> = borders.keys().copied().collect();
+        pairs.sort_by_key(|pair| (distance(pair), *pair));
+
+        //Kruskal's so every room is joined, with some of the rest added back for loops
+        let mut sets: Vec<usize> = (0..sites.len()).collect();
+        let mut chosen: Vec<(usize, usize)> = Vec::new();
+        let mut spare: Vec<(usize, usize)> = Vec::new();
+        for pair in pairs {
+            let (a, b) = (find_set(&mut sets, pair.0), find_set(&mut sets, pair.1));
+            if a != b {
+                sets[a] = b;
+                chosen.push(pair);
+            } else {
+                spare.push(pair);
+            }
+        }
+        spare.shuffle(&mut rng);
+        chosen.extend(spare.iter().take((spare.len() as f32 * self.extra_ratio.clamp(0.0, 1.0)).round() as usize));
+
+        let mut paths: Vec<DungeonPath> = Vec::new();
+        let mut edges: Vec<RoomEdge> = Vec::new();
+
+        for (a, b) in chosen {
+            //Cross in the middle of the border so corridors don't hug the corners
+            let border = &borders[&(a, b)];
+            let (from, to) = border[border.len() / 2];
+
+            let mut cells = VoronoiGenerator::dig_to_floor(&map, &owner, a, from);
+            cells.reverse();
+            cells.extend(VoronoiGenerator::dig_to_floor(&map, &owner, b, to));
+
+            //Only the wall tile right against a room's floor is a door, anything else dug through is corridor
+            let mut doors: Vec<(i32, i32)> = Vec::new();
+            for point in cells.iter() {
+                let against_floor = DIRECTIONS.iter().any(|dir| map.get((point.0 + dir.0, point.1 + dir.1)) == Some(Tile::Floor));
+                match map.get(*point) {
+                    Some(Tile::Wall) if against_floor => {
+                        map.set(*point, Tile::Door);
+                        doors.push(*point);
+                    }
+                    Some(Tile::Wall) | Some(Tile::Empty) => map.set(*point, Tile::Corridor),
+                    _ => {}
+                }
+            }
+
+            let mut path = DungeonPath::from_cells(&cells);
+            path.rooms = Some((a, b));
+
+            edges.push(RoomEdge {
+                path_idx: paths.len(),
+                rooms: (a, b),
+                length: path.cell_count(),
+                doors,
+                locked: None,
+            });
+            paths.push(path);
+        }
+
+        //A corridor can tunnel through the wall beside it, so walls are redrawn around everything open
+        map.wall_in();
+
+        for (id, cells) in floors.into_iter().enumerate() {
+            map.add_region(Region {
+                id,
+                bounds: DungeonMap::bounding_box(&cells),
+                cells,
+            });
+        }
+
+        Ok(VoronoiDungeon {
+            map,
+            paths,
+            room_graph: RoomGraph::from_edges((0..sites.len()).collect(), edges),
+        })
+    }
+
+    //Index of the closest site for every tile of the map, ties going to the earlier site
+    fn partition(map: &DungeonMap, sites: &[(i32, i32)]) -> Vec<usize> {
+        (0..map.len())
+            .map(|idx| {
+                let point = map.point(idx);
+                (0..sites.len())
+                    .min_by_key(|site| (sites[*site].0 - point.0).pow(2) + (sites[*site].1 - point.1).pow(2))
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+
+    //Tiles of each cell at least gap + 1 tiles in from its edge and the map's, leaving space for a wall.
+    //Only the biggest piece of a cell's floor is kept.
+    fn floors(&self, map: &DungeonMap, owner: &[usize], count: usize, inner: (i32, i32, i32, i32)) -> Vec<Vec<(i32, i32)>> {
+        let reach = self.gap.max(0) + 1;
+        let mut shrunk = DungeonMap::new(map.bounds);
+        let mut floors: Vec<Vec<(i32, i32)>> = vec![Vec::new(); count];
+
+        for idx in 0..map.len() {
+            let point = map.point(idx);
+            let clear = (-reach..=reach).all(|dy| {
+                (-reach..=reach).all(|dx| {
+                    let near = (point.0 + dx, point.1 + dy);
+                    rect_contains(inner, near) && map.index(near).is_some_and(|near| owner[near] == owner[idx])
+                })
+            });
+
+            if clear {
+                shrunk.set(point, Tile::Floor);
+            }
+        }
+
+        for pocket in shrunk.pockets() {
+            let site = owner[map.index(pocket[0]).unwrap()];
+            if floors[site].is_empty() {
+                floors[site] = pocket;
+            }
+        }
+
+        floors
+    }
+
+    //Shortest walk inside site's cell from start to the nearest floor tile, start included and the floor tile left out
+    fn dig_to_floor(map: &DungeonMap, owner: &[usize], site: usize, start: (i32, i32)) -> Vec<(i32, i32)> {
+        let mut parents: HashMap<(i32, i32), (i32, i32)> = HashMap::from([(start, start)]);
+        let mut queue: VecDeque<(i32, i32)> = VecDeque::from([start]);
+
+        while let Some(point) = queue.pop_front() {
+            if map.get(point) == Some(Tile::Floor) {
+                let mut cells: Vec<(i32, i32)> = Vec::new();
+                let mut curr = parents[&point];
+                while curr != start {
+                    cells.push(curr);
+                    curr = parents[&curr];
+                }
+                if point != start {
+                    cells.push(start);
+                }
+                cells.reverse();
+                return cells;
+            }
+
+            for dir in DIRECTIONS {
+                let next = (point.0 + dir.0, point.1 + dir.1);
+                if map.index(next).is_some_and(|idx| owner[idx] == site) && !parents.contains_key(&next) {
+                    parents.insert(next, point);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        vec![start]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::VoronoiGenerator;
+
+    #[test]
+    fn every_region_can_be_walked_to() {
+        for (seed, sites) in (0..12).zip([4, 12, 24].into_iter().cycle()) {
+            let voronoi = VoronoiGenerator {
+                sites,
+                ..VoronoiGenerator::default()
+            };
+            let dungeon = voronoi.generate_dungeon((0, 0, 80, 50), seed).unwrap();
+            let map = &dungeon.map;
+            let regions = map.regions();
+            assert!(!regions.is_empty() && regions.len() <= sites);
+
+            //On the map, and over the room graph's edges
+            assert_eq!(map.pockets().len(), 1, "\n{}", map.to_ascii());
+            let distances = map.distances_from(regions[0].center().unwrap());
+            for region in regions {
+                assert!(region.cells.iter().all(|cell| distances[map.index(*cell).unwrap()].is_some()));
+            }
+
+            let mut reached: HashSet<usize> = HashSet::from([regions[0].id]);
+            let mut queue: Vec<usize> = vec![regions[0].id];
+            while let Some(room) = queue.pop() {
+                for (other, _) in dungeon.room_graph.edges_of(room) {
+                    if reached.insert(other) {
+                        queue.push(other);
+                    }
+                }
+            }
+            assert_eq!(reached.len(), regions.len());
+
+            assert_eq!(voronoi.generate_dungeon((0, 0, 80, 50), seed).unwrap(), dungeon);
+        }
+    }
+}
@@ -0,0 +1,436 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+
+use crate::map::{DungeonMap, Region, Tile, DIRECTIONS};
+use crate::TreeError;
+
+//Most recent choices kept to back out of after a contradiction. Past that the whole map starts again.
+const SNAPSHOTS: usize = 16;
+
+//A few rooms and corridors to learn from when no sample is given
+const DEFAULT_SAMPLE: &str = "\
+*****************
+*.....*******#***
+*.....#######.***
+*.....*******.***
+***#*****.....***
+***#*****.....***
+***#######.....**
+*********.....***
+*****************
+";
+
+//Overlapping model wave function collapse. Every pattern_size by pattern_size square of the sample is a pattern,
+//and the map is filled so every square of it is one of those patterns, in about the same proportions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WfcGenerator {
+    //Rows of the sample, padded with empty tiles to the same length
+    sample: Vec<Vec<Tile>>,
+    pub pattern_size: usize,
+    //Also learn every pattern rotated and mirrored
+    pub symmetry: bool,
+    //Times a contradiction can be backed out of before giving up
+    pub max_retries: usize,
+}
+
+//Bits set for every pattern still allowed, one bitset per output cell
+type Wave = Vec<u64>;
+
+//What's been learned from the sample
+struct Model {
+    //Tiles of each pattern, row by row
+    patterns: Vec<Vec<Tile>>,
+    //How often each pattern shows up in the sample
+    weights: Vec<f64>,
+    //For each direction and pattern, the patterns that can sit next to it that way as a bitset
+    allowed: Vec<Vec<Vec<u64>>>,
+    words: usize,
+}
+
+impl Default for WfcGenerator {
+    fn default() -> WfcGenerator {
+        WfcGenerator::from_ascii(DEFAULT_SAMPLE).unwrap()
+    }
+}
+
+impl WfcGenerator {
+    //Reads a sample drawn with the characters DungeonMap::to_ascii and draw_to_file use.
+    //Blank lines at the end are ignored.
+    pub fn from_ascii(sample: &str) -> Result<WfcGenerator, TreeError> {
+        let mut rows: Vec<Vec<Tile>> = sample
+            .lines()
+            .map(|line| line.chars().map(|c| Tile::from_char(c).ok_or(TreeError::UnknownTileError(c))).collect())
+            .collect::<Result<_, _>>()?;
+
+        while rows.last().is_some_and(|row| row.is_empty()) {
+            rows.pop();
+        }
+
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        for row in rows.iter_mut() {
+            row.resize(width, Tile::Empty);
+        }
+
+        Ok(WfcGenerator {
+            sample: rows,
+            pattern_size: 3,
+            symmetry: true,
+            max_retries: 20,
+        })
+    }
+
+    //Reads a sample from a file, drawn the same way as for from_ascii
+    pub fn load(path: &Path) -> Result<WfcGenerator, TreeError> {
+        let sample = fs::read_to_string(path).map_err(|err| TreeError::SampleReadError(err.to_string()))?;
+
+        WfcGenerator::from_ascii(&sample)
+    }
+
+    //Each pocket of floor tiles in the result is a region, numbered from 0 biggest first.
+    //The same seed gives the same map.
+    pub fn generate(&self, bounds: (i32, i32, i32, i32), seed: u64) -> Result<DungeonMap, TreeError> {
+        let n = self.pattern_size.max(1);
+        let width = (bounds.2 - bounds.0).max(0) as usize;
+        let height = (bounds.3 - bounds.1).max(0) as usize;
+
+        if width < n || height < n {
+            return Err(TreeError::PatternSizeError(n));
+        }
+
+        let model = self.learn(n)?;
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        //One cell for every spot the top left corner of a pattern can go
+        let cells = (width - n + 1, height - n + 1);
+        let wave = model.run(cells, self.max_retries, &mut rng)?;
+
+        let mut map = DungeonMap::new(bounds);
+        for y in 0..height {
+            for x in 0..width {
+                let cell = (usize::min(x, cells.0 - 1), usize::min(y, cells.1 - 1));
+                let pattern = model.possible(&wave, cell.1 * cells.0 + cell.0)[0];
+                let tile = model.patterns[pattern][(y - cell.1) * n + (x - cell.0)];
+                map.set((bounds.0 + x as i32, bounds.1 + y as i32), tile);
+            }
+        }
+
+        //Rooms are the floor with everything else taken away
+        let mut floors = map.clone();
+        for idx in 0..floors.len() {
+            let point = floors.point(idx);
+            if floors.get(point) != Some(Tile::Floor) {
+                floors.set(point, Tile::Empty);
+            }
+        }
+
+        for (id, cells) in floors.pockets().into_iter().enumerate() {
+            map.add_region(Region {
+                id,
+                bounds: DungeonMap::bounding_box(&cells),
+                cells,
+            });
+        }
+
+        Ok(map)
+    }
+
+    fn learn(&self, n: usize) -> Result<Model, TreeError> {
+        let height = self.sample.len();
+        let width = self.sample.first().map_or(0, |row| row.len());
+
+        if width < n || height < n {
+            return Err(TreeError::PatternSizeError(n));
+        }
+
+        let mut patterns: Vec<Vec<Tile>> = Vec::new();
+        let mut weights: Vec<f64> = Vec::new();
+        let mut seen: HashMap<Vec<Tile>, usize> = HashMap::new();
+
+        for y in 0..=height - n {
+            for x in 0..=width - n {
+                let pattern: Vec<Tile> = (0..n * n).map(|idx| self.sample[y + idx / n][x + idx % n]).collect();
+
+                let mut variants = vec![pattern];
+                if self.symmetry {
+                    for i in 0..3 {
+                        variants.push(WfcGenerator::rotate(&variants[i], n));
+                    }
+                    for i in 0..4 {
+                        variants.push(WfcGenerator::mirror(&variants[i], n));
+                    }
+                }
+
+                for variant in variants {
+                    match seen.get(&variant) {
+                        Some(idx) => weights[*idx] += 1.0,
+                        None => {
+                            seen.insert(variant.clone(), patterns.len());
+                            patterns.push(variant);
+                            weights.push(1.0);
+                        }
+                    }
+                }
+            }
+        }
+
+        let words = patterns.len().div_ceil(64);
+        let allowed: Vec<Vec<Vec<u64>>> = DIRECTIONS
+            .iter()
+            .map(|dir| {
+                patterns
+                    .iter()
+                    .map(|p| {
+                        let mut bits = vec![0u64; words];
+                        for (q_idx, q) in patterns.iter().enumerate() {
+                            if WfcGenerator::agrees(p, q, *dir, n) {
+                                bits[q_idx / 64] |= 1 << (q_idx % 64);
+                            }
+                        }
+                        bits
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Ok(Model {
+            patterns,
+            weights,
+            allowed,
+            words,
+        })
+    }
+
+    //A quarter turn clockwise
+    fn rotate(pattern: &[Tile], n: usize) -> Vec<Tile> {
+        (0..n * n).map(|idx| pattern[(n - 1 - idx % n) * n + idx / n]).collect()
+    }
+
+    //Flipped left to right
+    fn mirror(pattern: &[Tile], n: usize) -> Vec<Tile> {
+        (0..n * n).map(|idx| pattern[(idx / n) * n + (n - 1 - idx % n)]).collect()
+    }
+
+    //Whether q can sit one step in dir from p, with the tiles where they overlap matching
+    fn agrees(p: &[Tile], q: &[Tile], dir: (i32, i32), n: usize) -> bool {
+        let n = n as i32;
+
+        (i32::max(0, dir.1)..i32::min(n, n + dir.1)).all(|y| {
+            (i32::max(0, dir.0)..i32::min(n, n + dir.0))
+                .all(|x| p[(y * n + x) as usize] == q[((y - dir.1) * n + (x - dir.0)) as usize])
+        })
+    }
+}
+
+impl Model {
+    fn possible(&self, wave: &Wave, cell: usize) -> Vec<usize> {
+        let mut possible: Vec<usize> = Vec::new();
+
+        for (word_idx, word) in wave[cell * self.words..(cell + 1) * self.words].iter().enumerate() {
+            let mut bits = *word;
+            while bits != 0 {
+                possible.push(word_idx * 64 + bits.trailing_zeros() as usize);
+                bits &= bits - 1;
+            }
+        }
+
+        possible
+    }
+
+    fn count(&self, wave: &Wave, cell: usize) -> u32 {
+        wave[cell * self.words..(cell + 1) * self.words].iter().map(|word| word.count_ones()).sum()
+    }
+
+    //Collapses cells one at a time, backing out of contradictions, until every cell has one pattern left
+    fn run<R: Rng>(&self, cells: (usize, usize), max_retries: usize, rng: &mut R) -> Result<Wave, TreeError> {
+        let mut full: Wave = vec![0; cells.0 * cells.1 * self.words];
+        for cell in 0..cells.0 * cells.1 {
+            for idx in 0..self.patterns.len() {
+                full[cell * self.words + idx / 64] |= 1 << (idx % 64);
+            }
+        }
+
+        let mut wave = full.clone();
+        let mut history: VecDeque<(Wave, usize, usize)> = VecDeque::new();
+        let mut retries = 0;
+
+        while let Some(cell) = self.lowest_entropy(&wave, cells, rng) {
+            let options = self.possible(&wave, cell);
+            let mut roll = rng.gen::<f64>() * options.iter().map(|idx| self.weights[*idx]).sum::<f64>();
+            let mut chosen = options[options.len() - 1];
+            for idx in options {
+                if roll < self.weights[idx] {
+                    chosen = idx;
+                    break;
+                }
+                roll -= self.weights[idx];
+            }
+
+            history.push_back((wave.clone(), cell, chosen));
+            if history.len() > SNAPSHOTS {
+                history.pop_front();
+            }
+
+            for word in 0..self.words {
+                wave[cell * self.words + word] = 0;
+            }
+            wave[cell * self.words + chosen / 64] |= 1 << (chosen % 64);
+
+            if self.propagate(&mut wave, cells, cell) {
+                continue;
+            }
+
+            //Go back to before the last choice and rule it out, further back if that fails too
+            loop {
+                retries += 1;
+                if retries > max_retries {
+                    return Err(TreeError::ContradictionError(max_retries));
+                }
+
+                match history.pop_back() {
+                    Some((saved, cell, chosen)) => {
+                        wave = saved;
+                        wave[cell * self.words + chosen / 64] &= !(1 << (chosen % 64));
+                        if self.propagate(&mut wave, cells, cell) {
+                            break;
+                        }
+                    }
+                    None => {
+                        wave = full.clone();
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(wave)
+    }
+
+    //Cell with the least Shannon entropy that still has more than one pattern, with a little noise to break ties.
+    //None once every cell is decided.
+    fn lowest_entropy<R: Rng>(&self, wave: &Wave, cells: (usize, usize), rng: &mut R) -> Option<usize> {
+        let mut best: Option<(usize, f64)> = None;
+
+        for cell in 0..cells.0 * cells.1 {
+            if self.count(wave, cell) <= 1 {
+                continue;
+            }
+
+            let (mut sum, mut sum_log) = (0.0, 0.0);
+            for (word_idx, word) in wave[cell * self.words..(cell + 1) * self.words].iter().enumerate() {
+                let mut bits = *word;
+                while bits != 0 {
+                    let weight = self.weights[word_idx * 64 + bits.trailing_zeros() as usize];
+                    sum += weight;
+                    sum_log += weight * weight.ln();
+                    bits &= bits - 1;
+                }
+            }
+            let entropy = sum.ln() - sum_log / sum + rng.gen::<f64>() * 1e-6;
+
+            if best.is_none_or(|best| entropy < best.1) {
+                best = Some((cell, entropy));
+            }
+        }
+
+        best.map(|(cell, _)| cell)
+    }
+
+    //Rules out patterns that no longer fit next to what changed, spreading out from start.
+    //False if some cell is left with nothing.
+    fn propagate(&self, wave: &mut Wave, cells: (usize, usize), start: usize) -> bool {
+        if self.count(wave, start) == 0 {
+            return false;
+        }
+
+        let mut stack: Vec<usize> = vec![start];
+
+        while let Some(cell) = stack.pop() {
+            let (x, y) = ((cell % cells.0) as i32, (cell / cells.0) as i32);
+            let options = self.possible(wave, cell);
+
+            for (dir_idx, dir) in DIRECTIONS.iter().enumerate() {
+                let next = (x + dir.0, y + dir.1);
+                if next.0 < 0 || next.1 < 0 || next.0 >= cells.0 as i32 || next.1 >= cells.1 as i32 {
+                    continue;
+                }
+                let next = next.1 as usize * cells.0 + next.0 as usize;
+
+                let mut fits = vec![0u64; self.words];
+                for idx in options.iter() {
+                    for (word, bits) in fits.iter_mut().zip(self.allowed[dir_idx][*idx].iter()) {
+                        *word |= bits;
+                    }
+                }
+
+                let mut changed = false;
+                for (word, bits) in wave[next * self.words..(next + 1) * self.words].iter_mut().zip(fits.iter()) {
+                    if *word & bits != *word {
+                        *word &= bits;
+                        changed = true;
+                    }
+                }
+
+                if changed {
+                    if self.count(wave, next) == 0 {
+                        return false;
+                    }
+                    stack.push(next);
+                }
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::WfcGenerator;
+    use crate::map::Tile;
+    use crate::TreeError;
+
+    #[test]
+    fn contradictions_are_backed_out_of_or_reported() {
+        //Each of these patterns can only have the other to its right or below it, so no map bigger than the sample fits
+        let stuck = WfcGenerator {
+            pattern_size: 2,
+            symmetry: false,
+            max_retries: 3,
+            ..WfcGenerator::from_ascii("*.#\n.#*\n").unwrap()
+        };
+        assert!(matches!(stuck.generate((0, 0, 4, 4), 0), Err(TreeError::ContradictionError(3))));
+
+        //Some seeds run into a contradiction with the built in sample, which retrying gets past
+        let mut wfc = WfcGenerator { max_retries: 0, ..WfcGenerator::default() };
+        let failed: Vec<u64> = (30..50).filter(|seed| wfc.generate((0, 0, 10, 8), *seed).is_err()).collect();
+        assert!(!failed.is_empty());
+
+        wfc.max_retries = 20;
+        let n = wfc.pattern_size;
+        let patterns: HashSet<Vec<Tile>> = wfc.learn(n).unwrap().patterns.into_iter().collect();
+
+        for seed in failed {
+            let map = wfc.generate((0, 0, 10, 8), seed).unwrap();
+
+            //Every square of the map is one of the sample's patterns
+            for y in 0..=8 - n as i32 {
+                for x in 0..=10 - n as i32 {
+                    let window: Vec<Tile> = (0..(n * n) as i32).map(|idx| map.get((x + idx % n as i32, y + idx / n as i32)).unwrap()).collect();
+                    assert!(patterns.contains(&window), "square at {:?} isn't in the sample", (x, y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bad_samples_and_sizes_are_reported() {
+        assert!(matches!(WfcGenerator::from_ascii("**\n*x\n"), Err(TreeError::UnknownTileError('x'))));
+        assert!(matches!(WfcGenerator::from_ascii("**\n**\n").unwrap().generate((0, 0, 10, 10), 0), Err(TreeError::PatternSizeError(3))));
+        assert!(matches!(WfcGenerator::default().generate((0, 0, 2, 10), 0), Err(TreeError::PatternSizeError(3))));
+    }
+}